@@ -2,7 +2,7 @@
 
 use egui::plot::Plot;
 use egui_extras::RetainedImage;
-use snail_nn::prelude::*;
+use snail_nn::{prelude::*, rng::Rng};
 use std::{
     sync::{Arc, Mutex},
     time::Duration,
@@ -25,24 +25,28 @@ fn main() {
     let h = std::thread::spawn(move || {
         let mut epoch = 0;
         let mut model = Model::new(&[3, 15, 9, 1]);
-        let mut batch = load_taining_data();
+        let batch = load_taining_data();
         let mut learning_rate = 0.0;
         let mut lerp = 0.0;
 
+        let mut rng = Rng::from_entropy();
+
         loop {
-            let (w, b) = model.gradient(&batch.next_chunk(32));
-            model.learn(w, b, learning_rate);
-            epoch += 1;
-
-            if epoch % 100 == 0 {
-                let output = imagine(100, 100, lerp, &model);
-                let cost = model.cost(&batch);
-                let mut c = ctx.lock().unwrap();
-                c.epoch = epoch;
-                c.out = output;
-                c.cost = cost;
-                learning_rate = c.learning_rate;
-                lerp = c.lerp;
+            for chunk in batch.shuffled(32, &mut rng) {
+                let gradients = model.gradient(&chunk);
+                model.learn(&gradients, learning_rate);
+                epoch += 1;
+
+                if epoch % 100 == 0 {
+                    let output = imagine(100, 100, lerp, &model);
+                    let cost = model.cost(&batch);
+                    let mut c = ctx.lock().unwrap();
+                    c.epoch = epoch;
+                    c.out = output;
+                    c.cost = cost;
+                    learning_rate = c.learning_rate;
+                    lerp = c.lerp;
+                }
             }
         }
     });
@@ -90,7 +94,7 @@ impl eframe::App for CatDogApp {
                 self.cost.last().unwrap_or(&0.0)
             ));
 
-            ui.style_mut().spacing.slider_width = (450.0).into();
+            ui.style_mut().spacing.slider_width = 450.0;
 
             ui.add(
                 egui::Slider::new(&mut self.context.lock().unwrap().learning_rate, 0.0..=5.0)
@@ -193,11 +197,11 @@ fn gray_to_egui(img: &image::GrayImage) -> RetainedImage {
     );
     RetainedImage::from_color_image("", color_image)
 }
-fn line_from_vec(vec: &Vec<f64>) -> egui::plot::Line {
+fn line_from_vec(vec: &[f64]) -> egui::plot::Line {
     egui::plot::Line::new(
         vec.iter()
             .enumerate()
-            .map(|(i, p)| [i as f64, p.clone()])
+            .map(|(i, p)| [i as f64, *p])
             .collect::<egui::plot::PlotPoints>(),
     )
 }
@@ -9,28 +9,22 @@ fn main() {
 
     let batch = TrainingBatch::new(input.clone(), output.clone());
 
-    let mut timer: u128 = 0;
-    let mut epoch: u128 = 0;
-
-    loop {
-        timer += 1;
-        let (w, b) = nn.gradient(&batch);
-        nn.learn(w, b, 1.0);
-        epoch += 1;
-
-        if timer % 100 == 0 {
+    let mut trainer = Trainer::new(100_000).with_callback(move |nn: &mut Model, metrics: &Metrics| {
+        if (metrics.epoch + 1) % 100 == 0 {
             //clear terminal
             print!("{esc}c", esc = 27 as char);
-            println!("epoch: {}",epoch);
-            println!("cost: {}", nn.cost(&batch));
+            println!("epoch: {}", metrics.epoch + 1);
+            println!("cost: {}", metrics.get("cost").unwrap());
 
             input.iter_rows().zip(output.iter_rows()).for_each(|(i, e)| {
-                let out = nn.forward(&i);
+                let out = nn.forward(i);
                 print!("in:[{:?}] ", &i);
                 print!("expected: {:?} --> ", e);
-                print!("out:[{:?}] ", out);
-                print!("\n");
+                println!("out:[{:?}] ", out);
             });
         }
-    }
+        Control::Continue
+    });
+
+    trainer.fit(&mut nn, &batch);
 }
@@ -1,12 +1,22 @@
+use std::sync::Arc;
+
+pub trait ActivationFn: Send + Sync {
+    fn forward(&self, x: f64) -> f64;
+    /// Derivative expressed in terms of the activated value `y = forward(x)`.
+    fn derivative(&self, y: f64) -> f64;
+    /// Unique name used to look the activation up in a `Registry` when loading.
+    fn name(&self) -> &str;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Activation {
     Sigmoid,
     Tanh,
     ReLU,
 }
 
-
-impl Activation {
-    pub fn forward(&self, f: f64) -> f64 {
+impl ActivationFn for Activation {
+    fn forward(&self, f: f64) -> f64 {
         match self {
             Activation::Sigmoid => sigmoid(f),
             Activation::Tanh => tanh(f),
@@ -14,15 +24,50 @@ impl Activation {
         }
     }
 
-    pub fn derivative(&self, f: f64) -> f64 {
+    fn derivative(&self, f: f64) -> f64 {
         match self {
             Activation::Sigmoid => sigmoid_derivative(f),
             Activation::Tanh => tanh_derivative(f),
             Activation::ReLU => relu_derivative(f),
         }
     }
+
+    fn name(&self) -> &str {
+        match self {
+            Activation::Sigmoid => "sigmoid",
+            Activation::Tanh => "tanh",
+            Activation::ReLU => "relu",
+        }
+    }
 }
 
+impl<T: ActivationFn + ?Sized> ActivationFn for Box<T> {
+    fn forward(&self, x: f64) -> f64 {
+        (**self).forward(x)
+    }
+
+    fn derivative(&self, y: f64) -> f64 {
+        (**self).derivative(y)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+impl<T: ActivationFn + ?Sized> ActivationFn for Arc<T> {
+    fn forward(&self, x: f64) -> f64 {
+        (**self).forward(x)
+    }
+
+    fn derivative(&self, y: f64) -> f64 {
+        (**self).derivative(y)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
 
 fn relu(x: f64) -> f64 {
     x.max(0.0)
@@ -0,0 +1,479 @@
+use crate::{batch::TrainingBatch, rng::Rng};
+use std::sync::Arc;
+
+/// Layout of the images in the input rows, pixels row by row with the
+/// channels of a pixel next to each other.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageShape {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+}
+
+impl ImageShape {
+    pub fn new(width: usize, height: usize, channels: usize) -> ImageShape {
+        ImageShape {
+            width,
+            height,
+            channels,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height * self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn index(&self, x: usize, y: usize, channel: usize) -> usize {
+        (y * self.width + x) * self.channels + channel
+    }
+}
+
+/// A random transform of a batch of images, drawn from `rng` only.
+pub trait Augmentation: Send + Sync {
+    fn apply(&self, batch: &mut TrainingBatch, shape: ImageShape, rng: &mut Rng);
+}
+
+impl<F: Fn(&mut TrainingBatch, ImageShape, &mut Rng) + Send + Sync> Augmentation for F {
+    fn apply(&self, batch: &mut TrainingBatch, shape: ImageShape, rng: &mut Rng) {
+        self(batch, shape, rng)
+    }
+}
+
+/// Augmentations applied in order to every mini-batch `Trainer` or
+/// `DataLoader` draws, the validation set is left alone.
+///
+/// The randomness of a batch only depends on the seed and its epoch and
+/// index, so runs and resumed runs repeat exactly.
+#[derive(Clone)]
+pub struct Augmenter {
+    shape: ImageShape,
+    seed: u64,
+    steps: Vec<Arc<dyn Augmentation>>,
+}
+
+impl Augmenter {
+    pub fn new(shape: ImageShape, seed: u64) -> Augmenter {
+        Augmenter {
+            shape,
+            seed,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, augmentation: impl Augmentation + 'static) -> Augmenter {
+        self.steps.push(Arc::new(augmentation));
+        self
+    }
+
+    pub fn shape(&self) -> ImageShape {
+        self.shape
+    }
+
+    pub fn apply(&self, batch: &mut TrainingBatch, rng: &mut Rng) {
+        assert_eq!(
+            batch.input.cols(),
+            self.shape.len(),
+            "inputs are not images of the shape"
+        );
+        for step in self.steps.iter() {
+            step.apply(batch, self.shape, rng);
+        }
+    }
+
+    /// `apply` with the generator of the `key`-th batch, `Trainer` and
+    /// `DataLoader` both pass `Augmenter::key` so a run sees the same
+    /// augmentations with either.
+    pub fn augment(&self, batch: &mut TrainingBatch, key: u64) {
+        self.apply(batch, &mut Rng::derive(self.seed, key));
+    }
+
+    /// Key of batch `index` in `epoch`, with `batches` batches per epoch.
+    pub fn key(epoch: usize, batches: usize, index: usize) -> u64 {
+        (epoch * batches + index) as u64
+    }
+}
+
+// draws one image at a time from the batch
+fn each_image(batch: &mut TrainingBatch, rng: &mut Rng, mut f: impl FnMut(&mut [f64], &mut Rng)) {
+    batch.input.iter_rows_mut().for_each(|image| f(image, rng));
+}
+
+/// Mirrors images with probability `probability`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flip {
+    horizontal: bool,
+    probability: f64,
+}
+
+impl Flip {
+    /// Left to right.
+    pub fn horizontal(probability: f64) -> Flip {
+        assert!((0.0..=1.0).contains(&probability));
+        Flip {
+            horizontal: true,
+            probability,
+        }
+    }
+
+    /// Top to bottom.
+    pub fn vertical(probability: f64) -> Flip {
+        assert!((0.0..=1.0).contains(&probability));
+        Flip {
+            horizontal: false,
+            probability,
+        }
+    }
+}
+
+impl Augmentation for Flip {
+    fn apply(&self, batch: &mut TrainingBatch, shape: ImageShape, rng: &mut Rng) {
+        each_image(batch, rng, |image, rng| {
+            if rng.uniform() >= self.probability {
+                return;
+            }
+            let source = image.to_vec();
+            for y in 0..shape.height {
+                for x in 0..shape.width {
+                    let (sx, sy) = match self.horizontal {
+                        true => (shape.width - 1 - x, y),
+                        false => (x, shape.height - 1 - y),
+                    };
+                    for c in 0..shape.channels {
+                        image[shape.index(x, y, c)] = source[shape.index(sx, sy, c)];
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Crops a random window of the image size out of the image padded with
+/// `padding` zeros, i.e. shifts it by up to `padding` pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RandomCrop {
+    padding: usize,
+}
+
+impl RandomCrop {
+    pub fn new(padding: usize) -> RandomCrop {
+        RandomCrop { padding }
+    }
+}
+
+impl Augmentation for RandomCrop {
+    fn apply(&self, batch: &mut TrainingBatch, shape: ImageShape, rng: &mut Rng) {
+        let padding = self.padding as isize;
+        each_image(batch, rng, |image, rng| {
+            let dx = rng.below(self.padding * 2 + 1) as isize - padding;
+            let dy = rng.below(self.padding * 2 + 1) as isize - padding;
+            let source = image.to_vec();
+            for y in 0..shape.height {
+                for x in 0..shape.width {
+                    let (sx, sy) = (x as isize + dx, y as isize + dy);
+                    let inside = (0..shape.width as isize).contains(&sx)
+                        && (0..shape.height as isize).contains(&sy);
+                    for c in 0..shape.channels {
+                        image[shape.index(x, y, c)] = match inside {
+                            true => source[shape.index(sx as usize, sy as usize, c)],
+                            false => 0.0,
+                        };
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Rotates around the center by up to `degrees` either way, bilinear with
+/// zeros outside the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rotate {
+    degrees: f64,
+}
+
+impl Rotate {
+    pub fn new(degrees: f64) -> Rotate {
+        assert!(degrees >= 0.0);
+        Rotate { degrees }
+    }
+}
+
+impl Augmentation for Rotate {
+    fn apply(&self, batch: &mut TrainingBatch, shape: ImageShape, rng: &mut Rng) {
+        each_image(batch, rng, |image, rng| {
+            let angle = (rng.uniform() * 2.0 - 1.0) * self.degrees.to_radians();
+            rotate(image, shape, angle);
+        });
+    }
+}
+
+fn rotate(image: &mut [f64], shape: ImageShape, angle: f64) {
+    let cx = (shape.width as f64 - 1.0) / 2.0;
+    let cy = (shape.height as f64 - 1.0) / 2.0;
+    let (sin, cos) = angle.sin_cos();
+    let source = image.to_vec();
+    let pixel = |x: isize, y: isize, c: usize| {
+        if x < 0 || y < 0 || x >= shape.width as isize || y >= shape.height as isize {
+            0.0
+        } else {
+            source[shape.index(x as usize, y as usize, c)]
+        }
+    };
+    for y in 0..shape.height {
+        for x in 0..shape.width {
+            // the source of an output pixel is rotated back
+            let (rx, ry) = (x as f64 - cx, y as f64 - cy);
+            let sx = cos * rx + sin * ry + cx;
+            let sy = -sin * rx + cos * ry + cy;
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as isize, y0 as isize);
+            for c in 0..shape.channels {
+                let top = pixel(x0, y0, c) * (1.0 - fx) + pixel(x0 + 1, y0, c) * fx;
+                let bottom = pixel(x0, y0 + 1, c) * (1.0 - fx) + pixel(x0 + 1, y0 + 1, c) * fx;
+                image[shape.index(x, y, c)] = top * (1.0 - fy) + bottom * fy;
+            }
+        }
+    }
+}
+
+/// Scales brightness by a factor in `1 ± brightness` and the distance to
+/// the image mean by a factor in `1 ± contrast`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorJitter {
+    brightness: f64,
+    contrast: f64,
+}
+
+impl ColorJitter {
+    pub fn new(brightness: f64, contrast: f64) -> ColorJitter {
+        assert!((0.0..=1.0).contains(&brightness) && (0.0..=1.0).contains(&contrast));
+        ColorJitter {
+            brightness,
+            contrast,
+        }
+    }
+}
+
+impl Augmentation for ColorJitter {
+    fn apply(&self, batch: &mut TrainingBatch, _: ImageShape, rng: &mut Rng) {
+        each_image(batch, rng, |image, rng| {
+            let brightness = 1.0 + (rng.uniform() * 2.0 - 1.0) * self.brightness;
+            let contrast = 1.0 + (rng.uniform() * 2.0 - 1.0) * self.contrast;
+            image.iter_mut().for_each(|v| *v *= brightness);
+            let mean = image.iter().sum::<f64>() / image.len() as f64;
+            image
+                .iter_mut()
+                .for_each(|v| *v = (*v - mean) * contrast + mean);
+        });
+    }
+}
+
+/// Adds normal noise of standard deviation `std` to every value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaussianNoise {
+    std: f64,
+}
+
+impl GaussianNoise {
+    pub fn new(std: f64) -> GaussianNoise {
+        assert!(std >= 0.0);
+        GaussianNoise { std }
+    }
+}
+
+impl Augmentation for GaussianNoise {
+    fn apply(&self, batch: &mut TrainingBatch, _: ImageShape, rng: &mut Rng) {
+        batch
+            .input
+            .iter_mut()
+            .for_each(|v| *v += rng.normal() * self.std);
+    }
+}
+
+/// Blends every sample and its target with another sample of the batch,
+/// the weight is drawn from `Beta(alpha, alpha)` once per batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mixup {
+    alpha: f64,
+}
+
+impl Mixup {
+    pub fn new(alpha: f64) -> Mixup {
+        assert!(alpha > 0.0);
+        Mixup { alpha }
+    }
+}
+
+// Marsaglia and Tsang
+fn gamma(shape: f64, rng: &mut Rng) -> f64 {
+    if shape < 1.0 {
+        return gamma(shape + 1.0, rng) * rng.uniform().powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = rng.normal();
+        let v = (1.0 + c * x).powi(3);
+        if v > 0.0 && (1.0 - rng.uniform()).ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+impl Augmentation for Mixup {
+    fn apply(&self, batch: &mut TrainingBatch, _: ImageShape, rng: &mut Rng) {
+        let a = gamma(self.alpha, rng);
+        let lambda = a / (a + gamma(self.alpha, rng));
+        let mut partners = (0..batch.samples()).collect::<Vec<usize>>();
+        rng.shuffle(&mut partners);
+
+        let other = batch.select(&partners);
+        for (mine, theirs) in [
+            (&mut batch.input, &other.input),
+            (&mut batch.expected, &other.expected),
+        ] {
+            mine.iter_mut()
+                .zip(theirs.iter())
+                .for_each(|(m, t)| *m = lambda * *m + (1.0 - lambda) * t);
+        }
+    }
+}
+
+#[cfg(test)]
+fn images(count: usize, shape: ImageShape) -> TrainingBatch {
+    use crate::mat::MatF64;
+
+    let values = (0..count * shape.len())
+        .map(|v| v as f64)
+        .collect::<Vec<f64>>();
+    let mut expected = MatF64::zeros(count, 2);
+    expected
+        .iter_rows_mut()
+        .enumerate()
+        .for_each(|(i, row)| row[i % 2] = 1.0);
+    TrainingBatch::new(MatF64::new(&values, count, shape.len()), expected)
+}
+
+#[test]
+fn test_geometric_augmentations() {
+    let shape = ImageShape::new(3, 2, 1);
+    let mut rng = Rng::new(0);
+
+    let mut batch = images(1, shape);
+    Flip::horizontal(1.0).apply(&mut batch, shape, &mut rng);
+    assert_eq!(batch.input.to_vec(), vec![2.0, 1.0, 0.0, 5.0, 4.0, 3.0]);
+    Flip::vertical(1.0).apply(&mut batch, shape, &mut rng);
+    assert_eq!(batch.input.to_vec(), vec![5.0, 4.0, 3.0, 2.0, 1.0, 0.0]);
+    Flip::vertical(0.0).apply(&mut batch, shape, &mut rng);
+    assert_eq!(batch.input.to_vec(), vec![5.0, 4.0, 3.0, 2.0, 1.0, 0.0]);
+
+    // a half turn of a two channel image is both flips
+    let shape = ImageShape::new(3, 3, 2);
+    let mut turned = images(1, shape);
+    let mut flipped = turned.clone();
+    Rotate::new(0.0).apply(&mut turned, shape, &mut rng);
+    assert_eq!(turned.input.to_vec(), flipped.input.to_vec());
+    rotate(
+        turned.input.iter_rows_mut().next().unwrap(),
+        shape,
+        std::f64::consts::PI,
+    );
+    Flip::horizontal(1.0).apply(&mut flipped, shape, &mut rng);
+    Flip::vertical(1.0).apply(&mut flipped, shape, &mut rng);
+    assert!(turned
+        .input
+        .iter()
+        .zip(flipped.input.iter())
+        .all(|(a, b)| (a - b).abs() < 1e-9));
+
+    // a crop is a shift by at most the padding, filled with zeros
+    let shape = ImageShape::new(3, 3, 1);
+    let source = images(1, shape);
+    let shifted = |dx: isize, dy: isize| {
+        let mut image = vec![0.0; 9];
+        for y in 0..3isize {
+            for x in 0..3isize {
+                if (0..3).contains(&(x + dx)) && (0..3).contains(&(y + dy)) {
+                    image[(y * 3 + x) as usize] =
+                        source.input.to_vec()[((y + dy) * 3 + x + dx) as usize];
+                }
+            }
+        }
+        image
+    };
+    let mut seen = Vec::new();
+    for seed in 0..30 {
+        let mut batch = source.clone();
+        RandomCrop::new(1).apply(&mut batch, shape, &mut Rng::new(seed));
+        let shift = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+            .find(|(dx, dy)| shifted(*dx, *dy) == batch.input.to_vec());
+        seen.push(shift.expect("not a shift of the image"));
+    }
+    seen.sort_unstable();
+    seen.dedup();
+    assert!(seen.len() > 4);
+}
+
+#[test]
+fn test_mixup_and_seeding() {
+    use crate::{dataset::Dataset, loader::DataLoader, nn::Model, train::Trainer};
+
+    let shape = ImageShape::new(2, 2, 1);
+    let batch = images(6, shape);
+    let mut mixed = batch.clone();
+    Mixup::new(0.4).apply(&mut mixed, shape, &mut Rng::new(1));
+    // blended one-hot targets still sum to one, inputs stay in the range
+    assert!(mixed
+        .expected
+        .iter_rows()
+        .all(|row| (row.iter().sum::<f64>() - 1.0).abs() < 1e-12));
+    assert!(mixed.input.iter().all(|v| (0.0..=23.0).contains(v)));
+    assert_ne!(mixed.input.to_vec(), batch.input.to_vec());
+
+    let augmenter = Augmenter::new(shape, 7)
+        .with(Flip::horizontal(0.5))
+        .with(ColorJitter::new(0.2, 0.2))
+        .with(GaussianNoise::new(0.1));
+    let augmented = |key| {
+        let mut copy = batch.clone();
+        augmenter.augment(&mut copy, key);
+        copy.input.to_vec()
+    };
+    assert_eq!(augmented(3), augmented(3));
+    assert_ne!(augmented(3), augmented(4));
+
+    // workers augment the batch with the key of its place in the run
+    let loader = DataLoader::new(batch.clone(), 4)
+        .with_workers(2)
+        .with_augmentation(augmenter.clone());
+    let loaded = loader
+        .epoch(1)
+        .map(|b| b.input.to_vec())
+        .collect::<Vec<_>>();
+    let expected = batch
+        .batches(4)
+        .enumerate()
+        .map(|(i, mut b)| {
+            augmenter.augment(&mut b, Augmenter::key(1, 2, i));
+            b.input.to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(loaded, expected);
+
+    let fit = || {
+        let mut model = Model::new(&[4, 3, 2]);
+        model.set_flat_params(&vec![0.1; model.param_count()]);
+        Trainer::new(3)
+            .with_batch_size(2)
+            .with_augmentation(augmenter.clone().with(Mixup::new(0.2)))
+            .fit(&mut model, &batch);
+        model.flat_params()
+    };
+    assert_eq!(fit(), fit());
+}
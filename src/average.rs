@@ -0,0 +1,214 @@
+use crate::nn::Model;
+
+/// Exponential moving average of a model's parameters, call `update` after every `learn`.
+#[derive(Clone, Debug)]
+pub struct Ema {
+    decay: f64,
+    warmup: bool,
+    updates: usize,
+    shadow: Vec<f64>,
+    // the model holds the average and `shadow` its own parameters
+    swapped: bool,
+}
+
+impl Ema {
+    pub fn new(model: &Model, decay: f64) -> Ema {
+        assert!((0.0..1.0).contains(&decay));
+        Ema {
+            decay,
+            warmup: false,
+            updates: 0,
+            shadow: model.flat_params(),
+            swapped: false,
+        }
+    }
+
+    /// Uses `min(decay, (1 + n) / (10 + n))` so early updates are not dominated
+    /// by the initial weights.
+    pub fn with_warmup(mut self) -> Ema {
+        self.warmup = true;
+        self
+    }
+
+    pub fn updates(&self) -> usize {
+        self.updates
+    }
+
+    pub fn decay(&self) -> f64 {
+        if self.warmup {
+            let n = self.updates as f64;
+            self.decay.min((1.0 + n) / (10.0 + n))
+        } else {
+            self.decay
+        }
+    }
+
+    /// Panics while the average is swapped into the model.
+    pub fn update(&mut self, model: &Model) {
+        assert!(!self.swapped, "swap the average back out before updating");
+        let decay = self.decay();
+        let params = model.flat_params();
+        assert_eq!(params.len(), self.shadow.len());
+        self.shadow
+            .iter_mut()
+            .zip(params.iter())
+            .for_each(|(s, p)| *s = decay * *s + (1.0 - decay) * p);
+        self.updates += 1;
+    }
+
+    pub fn params(&self) -> &[f64] {
+        &self.shadow
+    }
+
+    /// Overwrites the model's parameters with the average.
+    pub fn apply(&self, model: &mut Model) {
+        model.set_flat_params(&self.shadow);
+    }
+
+    /// Exchanges the model's parameters with the average, swapping twice restores both.
+    pub fn swap(&mut self, model: &mut Model) {
+        let current = model.flat_params();
+        model.set_flat_params(&self.shadow);
+        self.shadow = current;
+        self.swapped = !self.swapped;
+    }
+
+    /// True between two `swap` calls.
+    pub fn is_swapped(&self) -> bool {
+        self.swapped
+    }
+
+    /// A copy of `model` carrying the averaged parameters, e.g. for export.
+    pub fn averaged(&self, model: &Model) -> Model {
+        let mut out = model.clone();
+        self.apply(&mut out);
+        out
+    }
+}
+
+/// Stochastic weight averaging, an equal weight mean over the tail of training.
+/// Every `every`-th `update` starting at update `start` is accumulated.
+#[derive(Clone, Debug)]
+pub struct Swa {
+    start: usize,
+    every: usize,
+    updates: usize,
+    count: usize,
+    mean: Vec<f64>,
+    swapped: bool,
+}
+
+impl Swa {
+    pub fn new(start: usize, every: usize) -> Swa {
+        assert!(every > 0);
+        Swa {
+            start,
+            every,
+            updates: 0,
+            count: 0,
+            mean: Vec::new(),
+            swapped: false,
+        }
+    }
+
+    /// Number of snapshots in the average.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if the model was added to the average. Panics while the
+    /// average is swapped into the model.
+    pub fn update(&mut self, model: &Model) -> bool {
+        assert!(!self.swapped, "swap the average back out before updating");
+        let step = self.updates;
+        self.updates += 1;
+        if step < self.start || (step - self.start) % self.every != 0 {
+            return false;
+        }
+
+        let params = model.flat_params();
+        if self.count == 0 {
+            self.mean = params;
+        } else {
+            assert_eq!(params.len(), self.mean.len());
+            let n = (self.count + 1) as f64;
+            self.mean
+                .iter_mut()
+                .zip(params.iter())
+                .for_each(|(m, p)| *m += (p - *m) / n);
+        }
+        self.count += 1;
+        true
+    }
+
+    pub fn params(&self) -> Option<&[f64]> {
+        (self.count > 0).then_some(self.mean.as_slice())
+    }
+
+    pub fn apply(&self, model: &mut Model) {
+        model.set_flat_params(self.params().expect("swa has no snapshots yet"));
+    }
+
+    /// Exchanges the model's parameters with the average, swapping twice restores both.
+    pub fn swap(&mut self, model: &mut Model) {
+        assert!(self.count > 0, "swa has no snapshots yet");
+        let current = model.flat_params();
+        model.set_flat_params(&self.mean);
+        self.mean = current;
+        self.swapped = !self.swapped;
+    }
+
+    /// True between two `swap` calls.
+    pub fn is_swapped(&self) -> bool {
+        self.swapped
+    }
+
+    pub fn averaged(&self, model: &Model) -> Model {
+        let mut out = model.clone();
+        self.apply(&mut out);
+        out
+    }
+}
+
+#[test]
+fn test_ema() {
+    let mut model = Model::new(&[2, 2]);
+    let start = model.flat_params();
+    let mut ema = Ema::new(&model, 0.5);
+
+    let shifted = start.iter().map(|p| p + 1.0).collect::<Vec<f64>>();
+    model.set_flat_params(&shifted);
+    ema.update(&model);
+    ema.params()
+        .iter()
+        .zip(start.iter())
+        .for_each(|(e, s)| assert!((e - (s + 0.5)).abs() < 1e-12));
+
+    let average = ema.params().to_vec();
+    ema.swap(&mut model);
+    assert_eq!(model.flat_params(), average);
+    assert!(ema.is_swapped());
+    ema.swap(&mut model);
+    assert_eq!(model.flat_params(), shifted);
+    assert!(!ema.is_swapped());
+
+    assert_eq!(Ema::new(&model, 0.999).with_warmup().decay(), 0.1);
+}
+
+#[test]
+fn test_swa() {
+    let mut model = Model::new(&[1, 1]);
+    let mut swa = Swa::new(2, 2);
+    assert!(swa.params().is_none());
+
+    for step in 0..7 {
+        model.set_flat_params(&[step as f64, 0.0]);
+        swa.update(&model);
+    }
+    // steps 2, 4 and 6 are averaged
+    assert_eq!(swa.count(), 3);
+    assert_eq!(swa.params().unwrap(), &[4.0, 0.0]);
+
+    swa.apply(&mut model);
+    assert_eq!(model.flat_params(), vec![4.0, 0.0]);
+}
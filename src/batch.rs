@@ -1,9 +1,11 @@
 use crate::mat::MatF64;
 
+#[derive(Clone, Debug)]
 pub struct TrainingBatch {
     pub input: MatF64,
     pub expected: MatF64,
     index : usize,
+    weights: Option<Vec<f64>>,
 }
 
 impl TrainingBatch {
@@ -13,6 +15,7 @@ impl TrainingBatch {
             input: MatF64::empty(0, inpust_size),
             expected: MatF64::empty(0, output_size),
             index: 0,
+            weights: None,
         }
     }
 
@@ -23,11 +26,47 @@ impl TrainingBatch {
 
         self.input.add_row(input);
         self.expected.add_row(expected);
+        if let Some(weights) = self.weights.as_mut() {
+            weights.push(1.0);
+        }
+    }
+
+    /// Adds a sample with its weight, earlier samples weigh one if unset.
+    pub fn add_weighted(&mut self, input: &[f64], expected: &[f64], weight: f64) {
+        assert!(weight >= 0.0, "sample weights must not be negative");
+        let samples = self.samples();
+        self.weights.get_or_insert_with(|| vec![1.0; samples]);
+        self.add(input, expected);
+        *self.weights.as_mut().unwrap().last_mut().unwrap() = weight;
     }
 
     pub fn new(input: MatF64, expected: MatF64) -> TrainingBatch {
         assert_eq!(input.rows(), expected.rows());
-        TrainingBatch { input, expected, index: 0 }
+        TrainingBatch { input, expected, index: 0, weights: None }
+    }
+
+    /// One weight per sample, `Model::cost` and `Model::gradient` take the
+    /// weighted mean over the samples.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        self.set_weights(Some(weights));
+        self
+    }
+
+    pub fn set_weights(&mut self, weights: Option<Vec<f64>>) {
+        if let Some(weights) = &weights {
+            assert_eq!(weights.len(), self.samples(), "one weight per sample");
+            assert!(weights.iter().all(|w| *w >= 0.0), "sample weights must not be negative");
+        }
+        self.weights = weights;
+    }
+
+    /// `None` if every sample weighs one.
+    pub fn weights(&self) -> Option<&[f64]> {
+        self.weights.as_deref()
+    }
+
+    pub fn weight(&self, sample: usize) -> f64 {
+        self.weights.as_ref().map_or(1.0, |w| w[sample])
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&[f64], &[f64])> {
@@ -38,42 +77,87 @@ impl TrainingBatch {
         self.input.len()
     }
 
-    pub fn next_chunk(&mut self, size: usize) -> Self {
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Number of samples, `len` counts input values.
+    pub fn samples(&self) -> usize {
+        self.input.rows()
+    }
+
+    /// Class of every sample, see `label`.
+    pub fn labels(&self) -> Vec<usize> {
+        self.expected.iter_rows().map(label).collect()
+    }
+
+    /// Copies the samples at `indices` into a new batch, in that order.
+    pub fn select(&self, indices: &[usize]) -> Self {
 
         let mut input = MatF64::empty(0, self.input.cols());
         let mut expected = MatF64::empty(0, self.expected.cols());
 
+        for &index in indices {
+            input.add_row(self.input.get_row(index));
+            expected.add_row(self.expected.get_row(index));
+        }
+
+        let weights = self.weights.as_ref().map(|w| indices.iter().map(|i| w[*i]).collect());
+        TrainingBatch { weights, ..TrainingBatch::new(input, expected) }
+    }
+
+    /// Copies the samples `start..end` into a new batch.
+    pub fn slice(&self, start: usize, end: usize) -> Self {
+        assert!(start <= end && end <= self.input.rows());
+        self.select(&(start..end).collect::<Vec<usize>>())
+    }
+
+    /// Prefer `Dataset::shuffled`, this walks on with a random skip of up to two
+    /// samples and so repeats and misses some.
+    pub fn next_chunk(&mut self, size: usize) -> Self {
+
+        let mut indices = Vec::with_capacity(size);
+
         for i in 0..size {
 
             let offset = rand::random::<usize>() % 3;
-            let index = ( self.index + i + offset) % self.input.rows();
-
-            input.add_row(self.input.get_row(index));
-            expected.add_row(self.expected.get_row(index));
+            indices.push(( self.index + i + offset) % self.input.rows());
 
             self.index += 1;
         }
 
-        TrainingBatch::new(input, expected)
+        self.select(&indices)
     }
 
+    /// A contiguous window at a random offset, prefer `Dataset::shuffled`.
     pub fn random_chunk(&self, size: usize) -> Self {
 
-        let mut input = MatF64::empty(0, self.input.cols());
-        let mut expected = MatF64::empty(0, self.expected.cols());
-
         let offset = rand::random::<usize>();
 
+        let indices = (0..size)
+            .map(|i| ( offset + i ) % self.input.rows())
+            .collect::<Vec<usize>>();
 
-        for i in 0..size {
-
-            let index = ( offset + i ) % self.input.rows();
-
-            input.add_row(self.input.get_row(index));
-            expected.add_row(self.expected.get_row(index));
-
-        }
+        self.select(&indices)
+    }
+}
 
-        TrainingBatch::new(input, expected)
+/// Class of a target row, the rounded value of a single column, otherwise
+/// the index of the largest value as for one-hot targets.
+pub fn label(row: &[f64]) -> usize {
+    if row.len() == 1 {
+        assert!(row[0] > -0.5, "class labels must not be negative");
+        row[0].round() as usize
+    } else {
+        row.iter()
+            .enumerate()
+            .fold((0, f64::NEG_INFINITY), |best, (i, &v)| {
+                if v > best.1 {
+                    (i, v)
+                } else {
+                    best
+                }
+            })
+            .0
     }
 }
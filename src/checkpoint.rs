@@ -0,0 +1,231 @@
+use crate::save;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const PREFIX: &str = "checkpoint-";
+const EXTENSION: &str = "ckpt";
+
+/// Where and how often `Trainer` writes checkpoints and which ones it keeps.
+///
+/// Without `every` a checkpoint is written after every epoch. Without any
+/// retention every checkpoint is kept, otherwise the union of the last
+/// `keep_last` and the best `keep_best` by `metric` survives.
+#[derive(Clone, Debug)]
+pub struct Checkpoints {
+    dir: PathBuf,
+    every: Option<usize>,
+    keep_last: Option<usize>,
+    keep_best: usize,
+    metric: Option<String>,
+    maximize: bool,
+}
+
+/// A checkpoint file found on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Saved {
+    pub step: usize,
+    pub metric: Option<f64>,
+    pub path: PathBuf,
+}
+
+impl Checkpoints {
+    pub fn new(dir: impl Into<PathBuf>) -> Checkpoints {
+        Checkpoints {
+            dir: dir.into(),
+            every: None,
+            keep_last: None,
+            keep_best: 0,
+            metric: None,
+            maximize: false,
+        }
+    }
+
+    /// Checkpoints every `steps` optimizer steps instead of every epoch.
+    pub fn every(mut self, steps: usize) -> Checkpoints {
+        assert!(steps > 0);
+        self.every = Some(steps);
+        self
+    }
+
+    pub fn keep_last(mut self, count: usize) -> Checkpoints {
+        self.keep_last = Some(count);
+        self
+    }
+
+    /// Keeps the `count` checkpoints with the lowest `metric`, checkpoints
+    /// written at a step without the metric never count as best.
+    pub fn keep_best(mut self, metric: &str, count: usize) -> Checkpoints {
+        self.metric = Some(metric.to_string());
+        self.keep_best = count;
+        self
+    }
+
+    /// Higher values of the `keep_best` metric are better.
+    pub fn maximize(mut self) -> Checkpoints {
+        self.maximize = true;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn metric(&self) -> Option<&str> {
+        self.metric.as_deref()
+    }
+
+    pub(crate) fn interval(&self) -> Option<usize> {
+        self.every
+    }
+
+    /// Every checkpoint in the directory, oldest first.
+    pub fn list(&self) -> io::Result<Vec<Saved>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut saved = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_checkpoint = path.extension().is_some_and(|e| e == EXTENSION)
+                && path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(PREFIX));
+            if is_checkpoint {
+                let (step, metric) = read_header(&path)?;
+                saved.push(Saved { step, metric, path });
+            }
+        }
+        saved.sort_by_key(|s| s.step);
+        Ok(saved)
+    }
+
+    pub fn latest(&self) -> io::Result<Option<PathBuf>> {
+        Ok(self.list()?.pop().map(|s| s.path))
+    }
+
+    pub fn best(&self) -> io::Result<Option<PathBuf>> {
+        let mut saved = self.ranked()?;
+        Ok((!saved.is_empty()).then(|| saved.swap_remove(0).path))
+    }
+
+    // checkpoints carrying the metric, best first
+    fn ranked(&self) -> io::Result<Vec<Saved>> {
+        let mut saved: Vec<Saved> = self
+            .list()?
+            .into_iter()
+            .filter(|s| s.metric.is_some_and(|m| !m.is_nan()))
+            .collect();
+        saved.sort_by(|a, b| {
+            let order = a.metric.unwrap().total_cmp(&b.metric.unwrap());
+            if self.maximize {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        Ok(saved)
+    }
+
+    /// Writes a checkpoint through a temporary file so a crash never leaves
+    /// a truncated one behind, then applies the retention policy.
+    pub(crate) fn save(
+        &self,
+        step: usize,
+        metric: Option<f64>,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self
+            .dir
+            .join(format!("{}{:010}.{}", PREFIX, step, EXTENSION));
+        let tmp = path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        match metric {
+            Some(metric) => writeln!(writer, "snail_checkpoint 1 {} {}", step, metric)?,
+            None => writeln!(writer, "snail_checkpoint 1 {} none", step)?,
+        }
+        write(&mut writer)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        self.prune()?;
+        Ok(path)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        if self.keep_last.is_none() && self.keep_best == 0 {
+            return Ok(());
+        }
+        let saved = self.list()?;
+        let last = self.keep_last.unwrap_or(0).min(saved.len());
+        let mut keep: Vec<usize> = saved[saved.len() - last..].iter().map(|s| s.step).collect();
+        keep.extend(self.ranked()?.iter().take(self.keep_best).map(|s| s.step));
+
+        for s in saved.iter().filter(|s| !keep.contains(&s.step)) {
+            fs::remove_file(&s.path)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_header(path: &Path) -> io::Result<(usize, Option<f64>)> {
+    let mut line = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut line)?;
+    let mut reader = save::Reader::new(line.as_bytes())?;
+    reader.expect("snail_checkpoint")?;
+    reader.expect("1")?;
+    let step = reader.usize()?;
+    let metric = match reader.word()?.as_str() {
+        "none" => None,
+        word => Some(
+            word.parse()
+                .map_err(|_| save::invalid(format!("expected number, found `{}`", word)))?,
+        ),
+    };
+    Ok((step, metric))
+}
+
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("snail_nn_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn test_retention() {
+    let dir = test_dir("retention");
+    let checkpoints = Checkpoints::new(&dir).keep_last(2).keep_best("val_cost", 1);
+
+    for (step, metric) in [
+        (1, Some(0.5)),
+        (2, Some(0.2)),
+        (3, None),
+        (4, Some(0.4)),
+        (5, Some(0.3)),
+    ] {
+        checkpoints
+            .save(step, metric, |w| writeln!(w, "{}", step))
+            .unwrap();
+    }
+
+    let steps: Vec<usize> = checkpoints.list().unwrap().iter().map(|s| s.step).collect();
+    assert_eq!(steps, vec![2, 4, 5]);
+    assert_eq!(
+        checkpoints.latest().unwrap().unwrap(),
+        dir.join("checkpoint-0000000005.ckpt")
+    );
+    assert_eq!(
+        checkpoints.best().unwrap().unwrap(),
+        dir.join("checkpoint-0000000002.ckpt")
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,437 @@
+use crate::{batch::TrainingBatch, mat::MatF64, save::invalid};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// A column picked by its header name or by its zero based position.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Column {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Column {
+        Column::Name(name.to_string())
+    }
+}
+
+/// What to do with empty, `NA` or `NaN` fields in the selected columns.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Missing {
+    /// Fails with the line of the missing value.
+    #[default]
+    Fail,
+    /// Skips the rows with a missing value.
+    Drop,
+    /// Fills in the mean of the column's present values.
+    Mean,
+    Value(f64),
+}
+
+/// Reads delimited text into a `TrainingBatch` and writes matrices back.
+///
+/// Fields may be quoted with `"`, a quoted field can hold the delimiter,
+/// line breaks and `""` for a quote. Without `with_inputs` every column that
+/// is not a target is an input.
+#[derive(Clone, Debug)]
+pub struct Csv {
+    delimiter: char,
+    headers: bool,
+    inputs: Option<Vec<Column>>,
+    targets: Vec<Column>,
+    missing: Missing,
+}
+
+impl Default for Csv {
+    fn default() -> Csv {
+        Csv::new()
+    }
+}
+
+impl Csv {
+    /// Comma separated with a header line.
+    pub fn new() -> Csv {
+        Csv {
+            delimiter: ',',
+            headers: true,
+            inputs: None,
+            targets: Vec::new(),
+            missing: Missing::Fail,
+        }
+    }
+
+    /// Tab separated with a header line.
+    pub fn tsv() -> Csv {
+        Csv::new().with_delimiter('\t')
+    }
+
+    pub fn with_delimiter(mut self, delimiter: char) -> Csv {
+        assert!(delimiter != '"' && delimiter != '\n' && delimiter != '\r');
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// The first line is data, columns can only be picked by index.
+    pub fn without_headers(mut self) -> Csv {
+        self.headers = false;
+        self
+    }
+
+    pub fn with_inputs<C: Into<Column>>(mut self, columns: impl IntoIterator<Item = C>) -> Csv {
+        self.inputs = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_targets<C: Into<Column>>(mut self, columns: impl IntoIterator<Item = C>) -> Csv {
+        self.targets = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_missing(mut self, missing: Missing) -> Csv {
+        self.missing = missing;
+        self
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> io::Result<TrainingBatch> {
+        self.read_from(File::open(path)?)
+    }
+
+    pub fn read_from(&self, mut r: impl Read) -> io::Result<TrainingBatch> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let mut records = records(&text, self.delimiter)?
+            .into_iter()
+            .skip_while(|(_, fields)| fields.is_empty());
+
+        let names = match self.headers {
+            true => records.next().map(|(_, fields)| fields).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let records = records.collect::<Vec<_>>();
+        let width = match (names.is_empty(), records.first()) {
+            (false, _) => names.len(),
+            (true, Some((_, fields))) => fields.len(),
+            (true, None) => 0,
+        };
+
+        let targets = self.resolve(&self.targets, &names, width)?;
+        let inputs = match &self.inputs {
+            Some(columns) => self.resolve(columns, &names, width)?,
+            None => (0..width).filter(|c| !targets.contains(c)).collect(),
+        };
+        let columns = inputs
+            .iter()
+            .chain(targets.iter())
+            .copied()
+            .collect::<Vec<usize>>();
+
+        let mut rows = Vec::with_capacity(records.len());
+        let empty = [String::new()];
+        for (line, fields) in records.iter() {
+            // a blank line is a missing value when there is a single column
+            let fields = match (fields.is_empty(), width) {
+                (true, 1) => &empty[..],
+                (true, _) => continue,
+                (false, _) => &fields[..],
+            };
+            if fields.len() != width {
+                return Err(invalid(format!(
+                    "line {}: expected {} fields, found {}",
+                    line,
+                    width,
+                    fields.len()
+                )));
+            }
+            let mut row = Vec::with_capacity(columns.len());
+            for &column in columns.iter() {
+                let value = parse(&fields[column]).map_err(|field| {
+                    invalid(format!(
+                        "line {}: expected number in column {}, found `{}`",
+                        line,
+                        column_name(&names, column),
+                        field
+                    ))
+                })?;
+                if value.is_none() && self.missing == Missing::Fail {
+                    return Err(invalid(format!(
+                        "line {}: missing value in column {}",
+                        line,
+                        column_name(&names, column)
+                    )));
+                }
+                row.push(value);
+            }
+            rows.push(row);
+        }
+
+        let fill = match self.missing {
+            Missing::Mean => (0..columns.len())
+                .map(|c| {
+                    let present = rows.iter().filter_map(|r| r[c]).collect::<Vec<f64>>();
+                    present.iter().sum::<f64>() / present.len().max(1) as f64
+                })
+                .collect(),
+            Missing::Value(value) => vec![value; columns.len()],
+            Missing::Fail | Missing::Drop => Vec::new(),
+        };
+
+        let mut batch = TrainingBatch::empty(inputs.len(), targets.len());
+        for row in rows {
+            if self.missing == Missing::Drop && row.iter().any(Option::is_none) {
+                continue;
+            }
+            let row = row
+                .iter()
+                .enumerate()
+                .map(|(c, v)| v.unwrap_or_else(|| fill[c]))
+                .collect::<Vec<f64>>();
+            let (input, expected) = row.split_at(inputs.len());
+            batch.add(input, expected);
+        }
+        Ok(batch)
+    }
+
+    /// Writes `values` one row per line, `headers` may be empty.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        headers: &[&str],
+        values: &MatF64,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer, headers, values)?;
+        writer.flush()
+    }
+
+    pub fn write_to(
+        &self,
+        w: &mut impl Write,
+        headers: &[&str],
+        values: &MatF64,
+    ) -> io::Result<()> {
+        if !headers.is_empty() {
+            assert_eq!(headers.len(), values.cols(), "one header per column");
+            let line = headers
+                .iter()
+                .map(|h| self.quote(h))
+                .collect::<Vec<String>>();
+            writeln!(w, "{}", line.join(&self.delimiter.to_string()))?;
+        }
+        for row in values.iter_rows() {
+            let line = row.iter().map(f64::to_string).collect::<Vec<String>>();
+            writeln!(w, "{}", line.join(&self.delimiter.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn quote(&self, field: &str) -> String {
+        if field.contains([self.delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    fn resolve(
+        &self,
+        columns: &[Column],
+        names: &[String],
+        width: usize,
+    ) -> io::Result<Vec<usize>> {
+        columns
+            .iter()
+            .map(|column| match column {
+                Column::Index(index) if *index < width => Ok(*index),
+                Column::Index(index) => Err(invalid(format!(
+                    "column {} out of range, there are {} columns",
+                    index, width
+                ))),
+                Column::Name(name) => names
+                    .iter()
+                    .position(|n| n.trim() == name)
+                    .ok_or_else(|| invalid(format!("no column named `{}`", name))),
+            })
+            .collect()
+    }
+}
+
+fn column_name(names: &[String], column: usize) -> String {
+    match names.get(column) {
+        Some(name) => format!("`{}`", name.trim()),
+        None => column.to_string(),
+    }
+}
+
+// None for a missing value, the trimmed field if it is not a number
+fn parse(field: &str) -> Result<Option<f64>, &str> {
+    let field = field.trim();
+    if field.is_empty() || field.eq_ignore_ascii_case("na") || field.eq_ignore_ascii_case("nan") {
+        return Ok(None);
+    }
+    field.parse().map(Some).map_err(|_| field)
+}
+
+// records with the line they start on, blank lines have no fields
+fn records(text: &str, delimiter: char) -> io::Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            match chars.next() {
+                None => {
+                    fields.push(field);
+                    break;
+                }
+                Some('"') if field.trim().is_empty() && !quoted => {
+                    field.clear();
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            None => {
+                                return Err(invalid(format!(
+                                    "line {}: unterminated quoted field",
+                                    start
+                                )))
+                            }
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                        }
+                    }
+                    while chars
+                        .peek()
+                        .is_some_and(|c| (*c == ' ' || *c == '\t') && *c != delimiter)
+                    {
+                        chars.next();
+                    }
+                    if !matches!(chars.peek(), None | Some('\n') | Some('\r'))
+                        && chars.peek() != Some(&delimiter)
+                    {
+                        return Err(invalid(format!(
+                            "line {}: unexpected text after quoted field",
+                            line
+                        )));
+                    }
+                }
+                Some(c) if c == delimiter => {
+                    fields.push(std::mem::take(&mut field));
+                    quoted = false;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | Some('\r') => {
+                    line += 1;
+                    fields.push(field);
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if fields.len() == 1 && fields[0].trim().is_empty() && !quoted {
+            fields.clear();
+        }
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+#[test]
+fn test_read_csv() {
+    let text = "id,\"size, cm\",color,label\r\n\
+                1,2.5,\"red\",0\n\
+                \n\
+                2,,\"say \"\"hi\"\"\",1\n\
+                3,4.5,NA,1\n";
+    let csv = Csv::new().with_inputs(["size, cm", "id"]).with_targets([3]);
+
+    let error = csv.read_from(text.as_bytes()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 4: missing value in column `size, cm`"
+    );
+
+    let batch = csv
+        .clone()
+        .with_missing(Missing::Mean)
+        .read_from(text.as_bytes())
+        .unwrap();
+    assert_eq!(batch.input.to_vec(), vec![2.5, 1.0, 3.5, 2.0, 4.5, 3.0]);
+    assert_eq!(batch.expected.to_vec(), vec![0.0, 1.0, 1.0]);
+
+    let batch = csv
+        .with_missing(Missing::Drop)
+        .read_from(text.as_bytes())
+        .unwrap();
+    assert_eq!(batch.input.to_vec(), vec![2.5, 1.0, 4.5, 3.0]);
+
+    // quoted fields may span lines, errors point at the right line
+    let text = "a\tb\n\"1\n\"\t2\n3\tx\n";
+    let error = Csv::tsv().read_from(text.as_bytes()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 4: expected number in column `b`, found `x`"
+    );
+    let error = Csv::new()
+        .read_from("a,b\n1,2\n3\n".as_bytes())
+        .unwrap_err();
+    assert_eq!(error.to_string(), "line 3: expected 2 fields, found 1");
+
+    // with a single column an empty line is a missing value, not a blank one
+    let text = "\nx\n1\n\n3\n";
+    let error = Csv::new().read_from(text.as_bytes()).unwrap_err();
+    assert_eq!(error.to_string(), "line 4: missing value in column `x`");
+    let batch = Csv::new()
+        .with_missing(Missing::Value(0.0))
+        .read_from(text.as_bytes())
+        .unwrap();
+    assert_eq!(batch.input.to_vec(), vec![1.0, 0.0, 3.0]);
+}
+
+#[test]
+fn test_write_csv() {
+    let values = MatF64::new(&[0.25, -1.0, 3.0, 1e-7], 2, 2);
+    let mut out = Vec::new();
+    Csv::new()
+        .write_to(&mut out, &["p, 0", "p\"1"], &values)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out.clone()).unwrap(),
+        "\"p, 0\",\"p\"\"1\"\n0.25,-1\n3,0.0000001\n"
+    );
+
+    let batch = Csv::new()
+        .with_targets(["p\"1"])
+        .read_from(&out[..])
+        .unwrap();
+    assert_eq!(batch.input.to_vec(), vec![0.25, 3.0]);
+    assert_eq!(batch.expected.to_vec(), vec![-1.0, 1e-7]);
+
+    let batch = Csv::new()
+        .without_headers()
+        .read_from("1,2\n3,4\n".as_bytes())
+        .unwrap();
+    assert_eq!(batch.input.to_vec(), vec![1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(batch.expected.cols(), 0);
+}
@@ -0,0 +1,233 @@
+use crate::{
+    batch::TrainingBatch,
+    dataset::Dataset,
+    layer::Mode,
+    nn::Model,
+    split::Splitter,
+    train::{History, Metrics, Trainer},
+};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+type Score = Arc<dyn Fn(&Model, &TrainingBatch) -> f64 + Send + Sync>;
+
+/// K-fold cross validation, every fold trains a fresh model on the other
+/// folds and is scored on the held out one. Folds run in parallel.
+#[derive(Clone)]
+pub struct CrossValidation {
+    folds: usize,
+    seed: u64,
+    stratified: bool,
+    scores: Vec<(String, Score)>,
+}
+
+/// Scores of one fold's model in eval mode, "cost" on its training part,
+/// "val_cost" and every `with_metric` on the held out part.
+#[derive(Clone, Debug)]
+pub struct FoldReport {
+    pub fold: usize,
+    pub metrics: Metrics,
+    pub history: History,
+}
+
+#[derive(Clone, Debug)]
+pub struct CvReport {
+    pub folds: Vec<FoldReport>,
+}
+
+impl CrossValidation {
+    pub fn new(folds: usize, seed: u64) -> CrossValidation {
+        assert!(folds > 1, "need at least two folds");
+        CrossValidation {
+            folds,
+            seed,
+            stratified: false,
+            scores: Vec::new(),
+        }
+    }
+
+    /// Keeps the class proportions of `Dataset::labels` in every fold.
+    pub fn stratified(mut self) -> CrossValidation {
+        self.stratified = true;
+        self
+    }
+
+    /// Additional score of the held out fold, e.g. accuracy.
+    pub fn with_metric(
+        mut self,
+        name: &str,
+        score: impl Fn(&Model, &TrainingBatch) -> f64 + Send + Sync + 'static,
+    ) -> CrossValidation {
+        self.scores.push((name.to_string(), Arc::new(score)));
+        self
+    }
+
+    /// Training and held out sample indices of every fold.
+    pub fn folds<D: Dataset + ?Sized>(&self, data: &D) -> Vec<(Vec<usize>, Vec<usize>)> {
+        assert!(data.len() >= self.folds, "fewer samples than folds");
+        let mut splitter = Splitter::new(self.seed);
+        if self.stratified {
+            splitter = splitter.stratified();
+        }
+        let parts = splitter.indices(data, &vec![1.0 / self.folds as f64; self.folds]);
+        assert!(
+            parts.iter().all(|p| !p.is_empty()),
+            "a fold holds out no samples"
+        );
+        (0..self.folds)
+            .map(|fold| {
+                let mut train = parts
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != fold)
+                    .flat_map(|(_, p)| p.iter().copied())
+                    .collect::<Vec<usize>>();
+                train.sort_unstable();
+                (train, parts[fold].clone())
+            })
+            .collect()
+    }
+
+    /// `build` creates the model and trainer for a fold, the held out fold is
+    /// set as the trainer's validation set.
+    pub fn run<D, F>(&self, data: &D, build: F) -> CvReport
+    where
+        D: Dataset,
+        F: Fn(usize) -> (Model, Trainer) + Sync,
+    {
+        let folds = self
+            .folds(data)
+            .into_par_iter()
+            .enumerate()
+            .map(|(fold, (train, held_out))| {
+                let train = data.subset(train);
+                let held_out = data.batch(&held_out);
+                let (mut model, trainer) = build(fold);
+                let mut trainer = trainer.with_validation(held_out.clone());
+                let history = trainer.fit(&mut model, &train);
+
+                model.set_mode(Mode::Eval);
+                let mut metrics = Metrics::default();
+                if let Some(last) = history.last() {
+                    metrics.epoch = last.epoch;
+                    metrics.step = last.step;
+                }
+                metrics.set("cost", model.cost(&train.to_batch()));
+                metrics.set("val_cost", model.cost(&held_out));
+                for (name, score) in self.scores.iter() {
+                    metrics.set(name, score(&model, &held_out));
+                }
+                FoldReport {
+                    fold,
+                    metrics,
+                    history,
+                }
+            })
+            .collect();
+        CvReport { folds }
+    }
+}
+
+impl CvReport {
+    /// One value per fold that recorded `name`.
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.folds
+            .iter()
+            .filter_map(|f| f.metrics.get(name))
+            .collect()
+    }
+
+    pub fn mean(&self, name: &str) -> Option<f64> {
+        let values = self.values(name);
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    }
+
+    /// Sample standard deviation across folds.
+    pub fn std(&self, name: &str) -> Option<f64> {
+        let values = self.values(name);
+        let mean = self.mean(name)?;
+        if values.len() < 2 {
+            return Some(0.0);
+        }
+        let var =
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (values.len() - 1) as f64;
+        Some(var.sqrt())
+    }
+
+    /// Mean and standard deviation of every metric, by name.
+    pub fn summary(&self) -> Vec<(String, f64, f64)> {
+        let Some(first) = self.folds.first() else {
+            return Vec::new();
+        };
+        first
+            .metrics
+            .iter()
+            .map(|(name, _)| {
+                (
+                    name.to_string(),
+                    self.mean(name).unwrap(),
+                    self.std(name).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_cross_validation() {
+    use crate::mat::MatF64;
+
+    let mut batch = TrainingBatch::empty(2, 1);
+    for row in MatF64::rand(30, 2).iter_rows() {
+        let label = if row[0] > row[1] { 1.0 } else { 0.0 };
+        batch.add(row, &[label]);
+    }
+
+    let cv = CrossValidation::new(5, 7)
+        .stratified()
+        .with_metric("accuracy", |model, held_out| {
+            let out = model.net().infer(&held_out.input);
+            let hits = out
+                .iter()
+                .zip(held_out.labels())
+                .filter(|(o, l)| (**o > 0.5) == (*l == 1))
+                .count();
+            hits as f64 / held_out.samples() as f64
+        });
+
+    let folds = cv.folds(&batch);
+    let mut held_out = folds
+        .iter()
+        .flat_map(|(_, h)| h.clone())
+        .collect::<Vec<_>>();
+    held_out.sort_unstable();
+    assert_eq!(held_out, (0..30).collect::<Vec<usize>>());
+    assert!(folds.iter().all(|(t, h)| t.len() + h.len() == 30));
+
+    let report = cv.run(&batch, |_| {
+        (Model::new(&[2, 4, 1]), Trainer::new(50).with_rate(2.0))
+    });
+    assert_eq!(report.folds.len(), 5);
+    assert!(report.folds.iter().enumerate().all(|(i, f)| f.fold == i));
+    assert_eq!(report.values("val_cost").len(), 5);
+    assert_eq!(report.folds[0].history.epochs.len(), 50);
+    assert_eq!(report.folds[0].metrics.epoch, 49);
+
+    let summary = report.summary();
+    let names = summary.iter().map(|s| s.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["accuracy", "cost", "val_cost"]);
+    assert!(report.mean("accuracy").unwrap() > 0.0);
+    assert!(report.std("val_cost").unwrap() >= 0.0);
+
+    // many classes with fewer samples than folds
+    let mut small = TrainingBatch::empty(1, 1);
+    for i in 0..30 {
+        small.add(&[i as f64 / 30.0], &[(i / 3) as f64]);
+    }
+    let folds = CrossValidation::new(5, 3).stratified().folds(&small);
+    assert!(folds.iter().all(|(_, h)| h.len() == 6));
+    let report = CrossValidation::new(5, 3)
+        .stratified()
+        .run(&small, |_| (Model::new(&[1, 1]), Trainer::new(2)));
+    assert_eq!(report.values("val_cost").len(), 5);
+}
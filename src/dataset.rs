@@ -0,0 +1,452 @@
+use crate::{
+    batch::{label, TrainingBatch},
+    rng::Rng,
+};
+
+/// Samples that can be fetched one at a time, from memory, disk or a generator.
+///
+/// `TrainingBatch` implements it with `len` counting samples, unlike its
+/// inherent `len` which counts input values.
+pub trait Dataset: Send + Sync {
+    /// Number of samples.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn input_size(&self) -> usize;
+
+    fn output_size(&self) -> usize;
+
+    /// Input and expected output of the sample at `index`.
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>);
+
+    /// Every sample in order, override when reading sequentially is cheaper
+    /// than random access.
+    fn stream(&self) -> Box<dyn Iterator<Item = (Vec<f64>, Vec<f64>)> + '_> {
+        Box::new((0..self.len()).map(|i| self.get(i)))
+    }
+
+    /// Gathers the samples at `indices` into a batch, in that order.
+    fn batch(&self, indices: &[usize]) -> TrainingBatch {
+        let mut batch = TrainingBatch::empty(self.input_size(), self.output_size());
+        for &index in indices {
+            let (input, expected) = self.get(index);
+            batch.add(&input, &expected);
+        }
+        batch
+    }
+
+    /// Loads every sample into memory.
+    fn to_batch(&self) -> TrainingBatch {
+        let mut batch = TrainingBatch::empty(self.input_size(), self.output_size());
+        for (input, expected) in self.stream() {
+            batch.add(&input, &expected);
+        }
+        batch
+    }
+
+    /// Class of every sample, see `label`.
+    fn labels(&self) -> Vec<usize> {
+        self.stream()
+            .map(|(_, expected)| label(&expected))
+            .collect()
+    }
+
+    /// Mini-batches of `size` samples in order.
+    fn batches(&self, size: usize) -> Batches<'_, Self>
+    where
+        Self: Sized,
+    {
+        Batches::new(self, (0..self.len()).collect(), size)
+    }
+
+    /// One epoch of mini-batches over a fresh permutation, every sample is
+    /// visited exactly once. Calling it again with the same `rng` reshuffles.
+    fn shuffled(&self, size: usize, rng: &mut Rng) -> Batches<'_, Self>
+    where
+        Self: Sized,
+    {
+        let mut order = (0..self.len()).collect::<Vec<usize>>();
+        rng.shuffle(&mut order);
+        Batches::new(self, order, size)
+    }
+
+    /// A view of the samples at `indices`, nothing is copied.
+    fn subset(&self, indices: Vec<usize>) -> Subset<'_, Self>
+    where
+        Self: Sized,
+    {
+        assert!(indices.iter().all(|i| *i < self.len()));
+        Subset {
+            data: self,
+            indices,
+        }
+    }
+
+    /// Applies `f` to every sample when it is fetched.
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Vec<f64>, Vec<f64>) -> (Vec<f64>, Vec<f64>) + Send + Sync,
+    {
+        Map::new(self, f)
+    }
+}
+
+impl Dataset for TrainingBatch {
+    fn len(&self) -> usize {
+        self.samples()
+    }
+
+    fn input_size(&self) -> usize {
+        self.input.cols()
+    }
+
+    fn output_size(&self) -> usize {
+        self.expected.cols()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (
+            self.input.get_row(index).to_vec(),
+            self.expected.get_row(index).to_vec(),
+        )
+    }
+
+    fn batch(&self, indices: &[usize]) -> TrainingBatch {
+        self.select(indices)
+    }
+
+    fn to_batch(&self) -> TrainingBatch {
+        self.clone()
+    }
+
+    fn labels(&self) -> Vec<usize> {
+        TrainingBatch::labels(self)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for &D {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn input_size(&self) -> usize {
+        (**self).input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        (**self).output_size()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (**self).get(index)
+    }
+
+    fn stream(&self) -> Box<dyn Iterator<Item = (Vec<f64>, Vec<f64>)> + '_> {
+        (**self).stream()
+    }
+
+    fn batch(&self, indices: &[usize]) -> TrainingBatch {
+        (**self).batch(indices)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Box<D> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn input_size(&self) -> usize {
+        (**self).input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        (**self).output_size()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (**self).get(index)
+    }
+
+    fn stream(&self) -> Box<dyn Iterator<Item = (Vec<f64>, Vec<f64>)> + '_> {
+        (**self).stream()
+    }
+
+    fn batch(&self, indices: &[usize]) -> TrainingBatch {
+        (**self).batch(indices)
+    }
+}
+
+/// Samples produced on demand by a closure of the sample index.
+pub struct FromFn<F> {
+    len: usize,
+    input_size: usize,
+    output_size: usize,
+    f: F,
+}
+
+impl<F> FromFn<F>
+where
+    F: Fn(usize) -> (Vec<f64>, Vec<f64>) + Send + Sync,
+{
+    pub fn new(len: usize, input_size: usize, output_size: usize, f: F) -> FromFn<F> {
+        FromFn {
+            len,
+            input_size,
+            output_size,
+            f,
+        }
+    }
+}
+
+impl<F> Dataset for FromFn<F>
+where
+    F: Fn(usize) -> (Vec<f64>, Vec<f64>) + Send + Sync,
+{
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    fn output_size(&self) -> usize {
+        self.output_size
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        assert!(index < self.len, "sample index out of range");
+        let (input, expected) = (self.f)(index);
+        assert_eq!(input.len(), self.input_size);
+        assert_eq!(expected.len(), self.output_size);
+        (input, expected)
+    }
+}
+
+/// See `Dataset::subset`.
+pub struct Subset<'a, D: ?Sized> {
+    data: &'a D,
+    indices: Vec<usize>,
+}
+
+impl<D: ?Sized> Subset<'_, D> {
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Subset<'_, D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn input_size(&self) -> usize {
+        self.data.input_size()
+    }
+
+    fn output_size(&self) -> usize {
+        self.data.output_size()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        self.data.get(self.indices[index])
+    }
+
+    fn batch(&self, indices: &[usize]) -> TrainingBatch {
+        let indices = indices
+            .iter()
+            .map(|i| self.indices[*i])
+            .collect::<Vec<usize>>();
+        self.data.batch(&indices)
+    }
+}
+
+/// See `Dataset::map`, sizes are taken from the first transformed sample.
+pub struct Map<D, F> {
+    data: D,
+    f: F,
+    input_size: usize,
+    output_size: usize,
+}
+
+impl<D, F> Map<D, F>
+where
+    D: Dataset,
+    F: Fn(Vec<f64>, Vec<f64>) -> (Vec<f64>, Vec<f64>) + Send + Sync,
+{
+    fn new(data: D, f: F) -> Map<D, F> {
+        let (input_size, output_size) = if data.is_empty() {
+            (data.input_size(), data.output_size())
+        } else {
+            let (input, expected) = data.get(0);
+            let (input, expected) = f(input, expected);
+            (input.len(), expected.len())
+        };
+        Map {
+            data,
+            f,
+            input_size,
+            output_size,
+        }
+    }
+}
+
+impl<D, F> Dataset for Map<D, F>
+where
+    D: Dataset,
+    F: Fn(Vec<f64>, Vec<f64>) -> (Vec<f64>, Vec<f64>) + Send + Sync,
+{
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    fn output_size(&self) -> usize {
+        self.output_size
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        let (input, expected) = self.data.get(index);
+        (self.f)(input, expected)
+    }
+
+    fn stream(&self) -> Box<dyn Iterator<Item = (Vec<f64>, Vec<f64>)> + '_> {
+        Box::new(self.data.stream().map(|(i, e)| (self.f)(i, e)))
+    }
+}
+
+/// Mini-batches over an order of sample indices, see `Dataset::shuffled`.
+pub struct Batches<'a, D: ?Sized = TrainingBatch> {
+    data: &'a D,
+    order: Vec<usize>,
+    size: usize,
+    drop_last: bool,
+    position: usize,
+}
+
+impl<'a, D: Dataset + ?Sized> Batches<'a, D> {
+    pub(crate) fn new(data: &'a D, order: Vec<usize>, size: usize) -> Batches<'a, D> {
+        assert!(size > 0);
+        Batches {
+            data,
+            order,
+            size,
+            drop_last: false,
+            position: 0,
+        }
+    }
+
+    /// Skips the final batch if it would be smaller than the others.
+    pub fn drop_last(mut self) -> Batches<'a, D> {
+        self.drop_last = true;
+        self
+    }
+
+    /// Sample indices of the whole epoch, in visiting order.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+impl<D: Dataset + ?Sized> Iterator for Batches<'_, D> {
+    type Item = TrainingBatch;
+
+    fn next(&mut self) -> Option<TrainingBatch> {
+        let end = (self.position + self.size).min(self.order.len());
+        if self.position >= end || (self.drop_last && end - self.position < self.size) {
+            return None;
+        }
+        let chunk = self.data.batch(&self.order[self.position..end]);
+        self.position = end;
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.order.len() - self.position;
+        let count = if self.drop_last {
+            left / self.size
+        } else {
+            left.div_ceil(self.size)
+        };
+        (count, Some(count))
+    }
+
+    // resuming mid epoch skips without fetching samples
+    fn nth(&mut self, n: usize) -> Option<TrainingBatch> {
+        self.position = (self.position + n * self.size).min(self.order.len());
+        self.next()
+    }
+}
+
+impl<D: Dataset + ?Sized> ExactSizeIterator for Batches<'_, D> {}
+
+#[test]
+fn test_shuffled_batches() {
+    use crate::mat::MatF64;
+
+    let batch = TrainingBatch::new(
+        MatF64::new(&(0..10).map(|i| i as f64).collect::<Vec<f64>>(), 10, 1),
+        MatF64::zeros(10, 1),
+    );
+    let mut rng = Rng::new(4);
+
+    let epoch = batch.shuffled(4, &mut rng);
+    assert_eq!(epoch.len(), 3);
+    let mut seen = epoch
+        .flat_map(|b| b.input.to_vec())
+        .map(|x| x as usize)
+        .collect::<Vec<usize>>();
+    assert_ne!(seen, (0..10).collect::<Vec<usize>>());
+    seen.sort();
+    assert_eq!(seen, (0..10).collect::<Vec<usize>>());
+
+    let first = batch.shuffled(4, &mut rng).order().to_vec();
+    let second = batch.shuffled(4, &mut rng).order().to_vec();
+    assert_ne!(first, second);
+
+    let mut dropped = batch.shuffled(4, &mut rng).drop_last();
+    assert_eq!(dropped.len(), 2);
+    assert!(dropped.all(|b| b.samples() == 4));
+
+    let mut skipped = batch.batches(3);
+    assert_eq!(skipped.nth(3).unwrap().input.to_vec(), vec![9.0]);
+    assert!(skipped.next().is_none());
+}
+
+#[test]
+fn test_lazy_datasets() {
+    let squares = FromFn::new(6, 1, 1, |i| (vec![i as f64], vec![(i * i) as f64]));
+    assert_eq!(squares.len(), 6);
+    assert_eq!(squares.get(3), (vec![3.0], vec![9.0]));
+
+    let subset = squares.subset(vec![5, 1, 2]);
+    let batch = subset.batch(&[0, 2]);
+    assert_eq!(batch.input.to_vec(), vec![5.0, 2.0]);
+    assert_eq!(batch.expected.to_vec(), vec![25.0, 4.0]);
+
+    let scaled = squares.map(|input, expected| {
+        let mut input = input;
+        input.push(1.0);
+        (input, expected.iter().map(|e| e / 25.0).collect())
+    });
+    assert_eq!(scaled.input_size(), 2);
+    assert_eq!(scaled.to_batch().expected.get_row(5), &[1.0]);
+
+    let memory = scaled.to_batch();
+    assert_eq!(Dataset::len(&memory), 6);
+    assert_eq!(memory.get(4), scaled.get(4));
+
+    let mut model = crate::nn::Model::new(&[2, 1]);
+    let history = crate::train::Trainer::new(3)
+        .with_batch_size(4)
+        .with_shuffle(0)
+        .fit(&mut model, &scaled.subset(vec![0, 2, 4, 5, 3]));
+    assert_eq!(history.last().unwrap().step, 6);
+}
@@ -0,0 +1,332 @@
+use crate::{batch::label, mat::MatF64, save::invalid};
+use std::{fmt::Debug, io};
+
+/// What transforming a category not seen by `fit` does.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Unknown {
+    #[default]
+    Fail,
+    /// All zero one-hot group, `-1` ordinal code.
+    Ignore,
+}
+
+// the sorted distinct values of one column
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Categories<T> {
+    values: Vec<T>,
+}
+
+impl<T: Clone + Ord + Debug> Categories<T> {
+    fn fit<'a>(values: impl Iterator<Item = &'a T>) -> Categories<T>
+    where
+        T: 'a,
+    {
+        let mut values = values.cloned().collect::<Vec<T>>();
+        values.sort();
+        values.dedup();
+        Categories { values }
+    }
+
+    fn index(&self, value: &T, unknown: Unknown) -> io::Result<Option<usize>> {
+        match (self.values.binary_search(value), unknown) {
+            (Ok(index), _) => Ok(Some(index)),
+            (Err(_), Unknown::Ignore) => Ok(None),
+            (Err(_), Unknown::Fail) => Err(invalid(format!("unknown category {:?}", value))),
+        }
+    }
+
+    fn value(&self, index: usize) -> io::Result<T> {
+        self.values.get(index).cloned().ok_or_else(|| {
+            invalid(format!(
+                "code {} is not one of {} categories",
+                index,
+                self.values.len()
+            ))
+        })
+    }
+}
+
+// one category set per column of the rows
+fn fit_columns<T: Clone + Ord + Debug, R: AsRef<[T]>>(rows: &[R]) -> Vec<Categories<T>> {
+    let width = rows.first().map_or(0, |r| r.as_ref().len());
+    assert!(
+        rows.iter().all(|r| r.as_ref().len() == width),
+        "rows differ in length"
+    );
+    (0..width)
+        .map(|c| Categories::fit(rows.iter().map(|r| &r.as_ref()[c])))
+        .collect()
+}
+
+/// Maps class labels to `0..classes` and back, e.g. for targets.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LabelEncoder<T> {
+    classes: Categories<T>,
+}
+
+impl<T: Clone + Ord + Debug> LabelEncoder<T> {
+    pub fn new() -> LabelEncoder<T> {
+        LabelEncoder {
+            classes: Categories { values: Vec::new() },
+        }
+    }
+
+    pub fn fit(&mut self, labels: &[T]) {
+        self.classes = Categories::fit(labels.iter());
+    }
+
+    /// Known classes in code order.
+    pub fn classes(&self) -> &[T] {
+        &self.classes.values
+    }
+
+    pub fn transform(&self, labels: &[T]) -> io::Result<Vec<usize>> {
+        labels
+            .iter()
+            .map(|l| Ok(self.classes.index(l, Unknown::Fail)?.unwrap()))
+            .collect()
+    }
+
+    pub fn inverse_transform(&self, codes: &[usize]) -> io::Result<Vec<T>> {
+        codes.iter().map(|c| self.classes.value(*c)).collect()
+    }
+
+    /// One-hot rows to train a classifier on.
+    pub fn one_hot(&self, labels: &[T]) -> io::Result<MatF64> {
+        let mut mat = MatF64::zeros(labels.len(), self.classes.values.len());
+        for (row, code) in mat.iter_rows_mut().zip(self.transform(labels)?) {
+            row[code] = 1.0;
+        }
+        Ok(mat)
+    }
+
+    /// The class of a `Model::forward` output, the most active unit or the
+    /// rounded value of a single output, clamped to the known classes.
+    pub fn decode(&self, output: &[f64]) -> &T {
+        assert!(
+            !self.classes.values.is_empty(),
+            "label encoder is not fitted"
+        );
+        let last = self.classes.values.len() - 1;
+        let code = match output {
+            [value] => value.round().clamp(0.0, last as f64) as usize,
+            _ => label(output).min(last),
+        };
+        &self.classes.values[code]
+    }
+
+    /// `decode` of every row, e.g. of `Sequential::infer`.
+    pub fn decode_rows(&self, outputs: &MatF64) -> Vec<T> {
+        outputs
+            .iter_rows()
+            .map(|row| self.decode(row).clone())
+            .collect()
+    }
+}
+
+/// Encodes every categorical column as a group of 0/1 columns.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OneHotEncoder<T> {
+    columns: Vec<Categories<T>>,
+    unknown: Unknown,
+}
+
+impl<T: Clone + Ord + Debug> OneHotEncoder<T> {
+    pub fn new() -> OneHotEncoder<T> {
+        OneHotEncoder {
+            columns: Vec::new(),
+            unknown: Unknown::Fail,
+        }
+    }
+
+    pub fn with_unknown(mut self, unknown: Unknown) -> OneHotEncoder<T> {
+        self.unknown = unknown;
+        self
+    }
+
+    pub fn fit<R: AsRef<[T]>>(&mut self, rows: &[R]) {
+        self.columns = fit_columns(rows);
+    }
+
+    /// Known categories of `column` in encoded order.
+    pub fn categories(&self, column: usize) -> &[T] {
+        &self.columns[column].values
+    }
+
+    /// Width of the encoded rows.
+    pub fn encoded_size(&self) -> usize {
+        self.columns.iter().map(|c| c.values.len()).sum()
+    }
+
+    pub fn transform<R: AsRef<[T]>>(&self, rows: &[R]) -> io::Result<MatF64> {
+        let mut mat = MatF64::zeros(rows.len(), self.encoded_size());
+        for (out, row) in mat.iter_rows_mut().zip(rows) {
+            let row = row.as_ref();
+            assert_eq!(row.len(), self.columns.len(), "one value per fitted column");
+            let mut offset = 0;
+            for (value, categories) in row.iter().zip(self.columns.iter()) {
+                if let Some(index) = categories.index(value, self.unknown)? {
+                    out[offset + index] = 1.0;
+                }
+                offset += categories.values.len();
+            }
+        }
+        Ok(mat)
+    }
+
+    /// The most active category of every group, a group without any positive
+    /// value, like an ignored unknown category, is an error.
+    pub fn inverse_transform(&self, encoded: &MatF64) -> io::Result<Vec<Vec<T>>> {
+        assert_eq!(encoded.cols(), self.encoded_size());
+        encoded
+            .iter_rows()
+            .map(|row| {
+                let mut offset = 0;
+                self.columns
+                    .iter()
+                    .enumerate()
+                    .map(|(column, categories)| {
+                        let group = &row[offset..offset + categories.values.len()];
+                        offset += group.len();
+                        let best = (0..group.len())
+                            .max_by(|a, b| group[*a].total_cmp(&group[*b]))
+                            .filter(|best| group[*best] > 0.0)
+                            .ok_or_else(|| {
+                                invalid(format!("no category is set in column {}", column))
+                            })?;
+                        Ok(categories.values[best].clone())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Encodes every categorical column as the index of its category.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrdinalEncoder<T> {
+    columns: Vec<Categories<T>>,
+    unknown: Unknown,
+}
+
+impl<T: Clone + Ord + Debug> OrdinalEncoder<T> {
+    pub fn new() -> OrdinalEncoder<T> {
+        OrdinalEncoder {
+            columns: Vec::new(),
+            unknown: Unknown::Fail,
+        }
+    }
+
+    pub fn with_unknown(mut self, unknown: Unknown) -> OrdinalEncoder<T> {
+        self.unknown = unknown;
+        self
+    }
+
+    pub fn fit<R: AsRef<[T]>>(&mut self, rows: &[R]) {
+        self.columns = fit_columns(rows);
+    }
+
+    pub fn categories(&self, column: usize) -> &[T] {
+        &self.columns[column].values
+    }
+
+    pub fn transform<R: AsRef<[T]>>(&self, rows: &[R]) -> io::Result<MatF64> {
+        let mut mat = MatF64::zeros(rows.len(), self.columns.len());
+        for (out, row) in mat.iter_rows_mut().zip(rows) {
+            let row = row.as_ref();
+            assert_eq!(row.len(), self.columns.len(), "one value per fitted column");
+            for ((out, value), categories) in out.iter_mut().zip(row).zip(self.columns.iter()) {
+                *out = match categories.index(value, self.unknown)? {
+                    Some(index) => index as f64,
+                    None => -1.0,
+                };
+            }
+        }
+        Ok(mat)
+    }
+
+    /// Codes are rounded, codes outside the categories are an error.
+    pub fn inverse_transform(&self, encoded: &MatF64) -> io::Result<Vec<Vec<T>>> {
+        assert_eq!(encoded.cols(), self.columns.len());
+        encoded
+            .iter_rows()
+            .map(|row| {
+                row.iter()
+                    .zip(self.columns.iter())
+                    .map(|(code, categories)| {
+                        if code.round() < 0.0 {
+                            return Err(invalid(format!("negative code {}", code)));
+                        }
+                        categories.value(code.round() as usize)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_label_encoder() {
+    let labels = ["cat", "dog", "bird", "dog"];
+    let mut encoder = LabelEncoder::new();
+    encoder.fit(&labels);
+    assert_eq!(encoder.classes(), &["bird", "cat", "dog"]);
+    assert_eq!(encoder.transform(&labels).unwrap(), vec![1, 2, 0, 2]);
+    assert_eq!(
+        encoder.inverse_transform(&[2, 0]).unwrap(),
+        vec!["dog", "bird"]
+    );
+    assert!(encoder.transform(&["fish"]).is_err());
+    assert!(encoder.inverse_transform(&[3]).is_err());
+
+    let targets = encoder.one_hot(&labels[..2]).unwrap();
+    assert_eq!(targets.to_vec(), vec![0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(*encoder.decode(&[0.1, 0.2, 0.7]), "dog");
+    assert_eq!(encoder.decode_rows(&targets), vec!["cat", "dog"]);
+
+    let mut binary = LabelEncoder::new();
+    binary.fit(&[false, true]);
+    assert!(*binary.decode(&[0.8]));
+    assert!(!*binary.decode(&[-0.9]));
+    assert!(*binary.decode(&[3.0]));
+}
+
+#[test]
+fn test_feature_encoders() {
+    let rows = [["red", "s"], ["green", "m"], ["red", "l"]];
+
+    let mut one_hot = OneHotEncoder::new();
+    one_hot.fit(&rows);
+    assert_eq!(one_hot.categories(1), &["l", "m", "s"]);
+    assert_eq!(one_hot.encoded_size(), 5);
+    let encoded = one_hot.transform(&rows[..2]).unwrap();
+    assert_eq!(
+        encoded.to_vec(),
+        vec![0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]
+    );
+    assert_eq!(
+        one_hot.inverse_transform(&encoded).unwrap(),
+        vec![rows[0], rows[1]]
+    );
+    assert!(one_hot.transform(&[["blue", "s"]]).is_err());
+    let ignored = one_hot
+        .clone()
+        .with_unknown(Unknown::Ignore)
+        .transform(&[["blue", "s"]])
+        .unwrap();
+    assert_eq!(ignored.to_vec(), vec![0.0, 0.0, 0.0, 0.0, 1.0]);
+    let error = one_hot.inverse_transform(&ignored).unwrap_err();
+    assert_eq!(error.to_string(), "no category is set in column 0");
+
+    let mut ordinal = OrdinalEncoder::new().with_unknown(Unknown::Ignore);
+    ordinal.fit(&rows);
+    let encoded = ordinal.transform(&[["green", "s"], ["blue", "m"]]).unwrap();
+    assert_eq!(encoded.to_vec(), vec![0.0, 2.0, -1.0, 1.0]);
+    assert_eq!(
+        ordinal
+            .inverse_transform(&MatF64::new(&[1.0, 0.2], 1, 2))
+            .unwrap(),
+        vec![vec!["red", "l"]]
+    );
+    assert!(ordinal.inverse_transform(&encoded).is_err());
+}
@@ -0,0 +1,238 @@
+use crate::mat::MatF64;
+use std::ops;
+
+/// Gradients of a model, one weight and one bias matrix per parametrized layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradients {
+    pub weights: Vec<MatF64>,
+    pub biases: Vec<MatF64>,
+}
+
+impl From<(Vec<MatF64>, Vec<MatF64>)> for Gradients {
+    fn from((weights, biases): (Vec<MatF64>, Vec<MatF64>)) -> Self {
+        Gradients::new(weights, biases)
+    }
+}
+
+impl Gradients {
+    pub fn new(weights: Vec<MatF64>, biases: Vec<MatF64>) -> Gradients {
+        assert_eq!(weights.len(), biases.len());
+        Gradients { weights, biases }
+    }
+
+    pub fn into_parts(self) -> (Vec<MatF64>, Vec<MatF64>) {
+        (self.weights, self.biases)
+    }
+
+    pub fn zeros_like(&self) -> Gradients {
+        Gradients::new(
+            self.weights.iter().map(MatF64::clone_zero).collect(),
+            self.biases.iter().map(MatF64::clone_zero).collect(),
+        )
+    }
+
+    pub fn layers(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &f64> {
+        self.weights
+            .iter()
+            .zip(self.biases.iter())
+            .flat_map(|(w, b)| w.iter().chain(b.iter()))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        self.weights
+            .iter_mut()
+            .zip(self.biases.iter_mut())
+            .flat_map(|(w, b)| w.iter_mut().chain(b.iter_mut()))
+    }
+
+    /// Global L2 norm over every weight and bias.
+    pub fn norm(&self) -> f64 {
+        self.iter().map(|x| x * x).sum::<f64>().sqrt()
+    }
+
+    /// L2 norm of each layer, weights and biases together.
+    pub fn layer_norms(&self) -> Vec<f64> {
+        self.weights
+            .iter()
+            .zip(self.biases.iter())
+            .map(|(w, b)| w.iter().chain(b.iter()).map(|x| x * x).sum::<f64>().sqrt())
+            .collect()
+    }
+
+    /// Flattened layer by layer, weights before biases, like `Model::flat_params`.
+    pub fn to_vec(&self) -> Vec<f64> {
+        self.iter().copied().collect()
+    }
+
+    pub fn dot(&self, other: &Gradients) -> f64 {
+        self.assert_aligned(other);
+        self.iter().zip(other.iter()).map(|(a, b)| a * b).sum()
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        self.iter_mut().for_each(|x| *x *= factor);
+    }
+
+    /// `self += factor * other`
+    pub fn add_scaled(&mut self, other: &Gradients, factor: f64) {
+        self.assert_aligned(other);
+        self.iter_mut()
+            .zip(other.iter())
+            .for_each(|(a, b)| *a += factor * b);
+    }
+
+    fn assert_aligned(&self, other: &Gradients) {
+        assert_eq!(self.layers(), other.layers());
+        for i in 0..self.layers() {
+            assert_eq!(self.weights[i].len(), other.weights[i].len());
+            assert_eq!(self.biases[i].len(), other.biases[i].len());
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.iter().all(|x| x.is_finite())
+    }
+
+    /// Rescales so the global norm is at most `max_norm`, returns the norm before clipping.
+    pub fn clip_norm(&mut self, max_norm: f64) -> f64 {
+        assert!(max_norm > 0.0);
+        let norm = self.norm();
+        if norm > max_norm {
+            let scale = max_norm / norm;
+            self.iter_mut().for_each(|x| *x *= scale);
+        }
+        norm
+    }
+
+    pub fn clip_value(&mut self, max: f64) {
+        assert!(max > 0.0);
+        self.iter_mut().for_each(|x| *x = x.clamp(-max, max));
+    }
+}
+
+impl ops::AddAssign<&Gradients> for Gradients {
+    fn add_assign(&mut self, other: &Gradients) {
+        self.add_scaled(other, 1.0);
+    }
+}
+
+impl ops::SubAssign<&Gradients> for Gradients {
+    fn sub_assign(&mut self, other: &Gradients) {
+        self.add_scaled(other, -1.0);
+    }
+}
+
+impl ops::MulAssign<f64> for Gradients {
+    fn mul_assign(&mut self, factor: f64) {
+        self.scale(factor);
+    }
+}
+
+impl ops::Add<&Gradients> for &Gradients {
+    type Output = Gradients;
+    fn add(self, other: &Gradients) -> Self::Output {
+        let mut out = self.clone();
+        out += other;
+        out
+    }
+}
+
+impl ops::Sub<&Gradients> for &Gradients {
+    type Output = Gradients;
+    fn sub(self, other: &Gradients) -> Self::Output {
+        let mut out = self.clone();
+        out -= other;
+        out
+    }
+}
+
+impl ops::Mul<f64> for &Gradients {
+    type Output = Gradients;
+    fn mul(self, factor: f64) -> Self::Output {
+        let mut out = self.clone();
+        out *= factor;
+        out
+    }
+}
+
+/// Weighted running sum of gradients from several micro-batches. Weighting every
+/// gradient by its batch length makes `mean` equal the gradient of the whole batch.
+#[derive(Clone, Debug, Default)]
+pub struct Accumulator {
+    sum: Option<Gradients>,
+    weight: f64,
+}
+
+impl Accumulator {
+    pub fn new() -> Accumulator {
+        Accumulator::default()
+    }
+
+    pub fn add(&mut self, gradients: &Gradients, weight: f64) {
+        assert!(weight >= 0.0);
+        match self.sum.as_mut() {
+            Some(sum) => sum.add_scaled(gradients, weight),
+            None => self.sum = Some(gradients * weight),
+        }
+        self.weight += weight;
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub fn sum(&self) -> Option<&Gradients> {
+        self.sum.as_ref()
+    }
+
+    pub fn mean(&self) -> Option<Gradients> {
+        if self.weight == 0.0 {
+            return None;
+        }
+        self.sum.as_ref().map(|sum| sum * (1.0 / self.weight))
+    }
+
+    pub fn clear(&mut self) {
+        self.sum = None;
+        self.weight = 0.0;
+    }
+}
+
+#[test]
+fn test_clip() {
+    let mut grads = Gradients::new(
+        vec![crate::mat!((3.0, 0.0))],
+        vec![crate::mat!((0.0, -4.0))],
+    );
+    assert_eq!(grads.norm(), 5.0);
+    assert_eq!(grads.layer_norms(), vec![5.0]);
+
+    assert_eq!(grads.clip_norm(10.0), 5.0);
+    assert_eq!(grads.norm(), 5.0);
+    assert_eq!(grads.clip_norm(1.0), 5.0);
+    assert!((grads.norm() - 1.0).abs() < 1e-12);
+
+    grads.clip_value(0.5);
+    assert_eq!(grads.weights[0], crate::mat!((0.5, 0.0)));
+    assert_eq!(grads.biases[0], crate::mat!((0.0, -0.5)));
+}
+
+#[test]
+fn test_arithmetic() {
+    let a = Gradients::new(vec![crate::mat!((1.0, 2.0))], vec![crate::mat!((3.0))]);
+    let b = &a * 2.0;
+    assert_eq!(a.dot(&b), 28.0);
+    assert_eq!(&b - &a, a);
+    assert_eq!((&a + &a), b);
+    assert_eq!(a.zeros_like().norm(), 0.0);
+
+    let mut acc = Accumulator::new();
+    assert!(acc.mean().is_none());
+    acc.add(&a, 1.0);
+    acc.add(&b, 3.0);
+    assert_eq!(acc.mean().unwrap(), &a * 1.75);
+}
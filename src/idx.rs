@@ -0,0 +1,188 @@
+use crate::{batch::TrainingBatch, mat::MatF64, save::invalid};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+/// An uncompressed IDX file as used by MNIST, Fashion-MNIST and EMNIST.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Idx {
+    pub dims: Vec<usize>,
+    pub values: Vec<f64>,
+    bytes: bool,
+}
+
+impl Idx {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Idx> {
+        Idx::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from(mut r: impl Read) -> io::Result<Idx> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+        if data.len() < 4 || data[0] != 0 || data[1] != 0 {
+            return Err(invalid(
+                "not an idx file, gzipped files must be unpacked first",
+            ));
+        }
+        let (code, rank) = (data[2], data[3] as usize);
+        let size = match code {
+            0x08 | 0x09 => 1,
+            0x0B => 2,
+            0x0C | 0x0D => 4,
+            0x0E => 8,
+            _ => return Err(invalid(format!("unknown idx type 0x{:02x}", code))),
+        };
+
+        let start = 4 + 4 * rank;
+        if data.len() < start {
+            return Err(invalid("idx header is truncated"));
+        }
+        let dims = data[4..start]
+            .chunks(4)
+            .map(|d| u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as usize)
+            .collect::<Vec<usize>>();
+        let product = |dims: &[usize]| dims.iter().try_fold(1usize, |n, d| n.checked_mul(*d));
+        let overflow = || invalid("idx dimensions overflow");
+        // a zero sample count would hide an overflowing sample size
+        product(dims.get(1..).unwrap_or_default()).ok_or_else(overflow)?;
+        let expected = product(&dims)
+            .and_then(|count| count.checked_mul(size))
+            .ok_or_else(overflow)?;
+        if data.len() - start != expected {
+            return Err(invalid(format!(
+                "expected {} bytes of idx data, found {}",
+                expected,
+                data.len() - start
+            )));
+        }
+
+        let values = data[start..]
+            .chunks(size)
+            .map(|b| match code {
+                0x08 => b[0] as f64,
+                0x09 => b[0] as i8 as f64,
+                0x0B => i16::from_be_bytes([b[0], b[1]]) as f64,
+                0x0C => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                0x0D => f32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                _ => f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+            })
+            .collect();
+        Ok(Idx {
+            dims,
+            values,
+            bytes: code == 0x08,
+        })
+    }
+
+    /// Size of the first dimension.
+    pub fn samples(&self) -> usize {
+        self.dims.first().copied().unwrap_or(0)
+    }
+
+    /// Values per sample, e.g. 784 for 28x28 images.
+    pub fn sample_size(&self) -> usize {
+        self.dims.iter().skip(1).product()
+    }
+
+    /// Samples as rows, unsigned bytes scaled to `[0, 1]`.
+    pub fn to_mat(&self) -> MatF64 {
+        let mut mat = MatF64::new(&self.values, self.samples(), self.sample_size());
+        if self.bytes {
+            mat.iter_mut().for_each(|v| *v /= 255.0);
+        }
+        mat
+    }
+}
+
+/// Images with one-hot targets over `classes`, labels must be below `classes`.
+pub fn idx_batch(images: &Idx, labels: &Idx, classes: usize) -> io::Result<TrainingBatch> {
+    if labels.sample_size() != 1 {
+        return Err(invalid("expected one label per sample"));
+    }
+    if images.samples() != labels.samples() {
+        return Err(invalid(format!(
+            "{} images but {} labels",
+            images.samples(),
+            labels.samples()
+        )));
+    }
+    let mut expected = MatF64::zeros(labels.samples(), classes);
+    for (row, label) in expected.iter_rows_mut().zip(labels.values.iter()) {
+        if label.fract() != 0.0 || *label < 0.0 || *label >= classes as f64 {
+            return Err(invalid(format!(
+                "label {} is not one of {} classes",
+                label, classes
+            )));
+        }
+        row[*label as usize] = 1.0;
+    }
+    Ok(TrainingBatch::new(images.to_mat(), expected))
+}
+
+/// Reads an images and a labels file, see `idx_batch`.
+pub fn load_idx(
+    images: impl AsRef<Path>,
+    labels: impl AsRef<Path>,
+    classes: usize,
+) -> io::Result<TrainingBatch> {
+    idx_batch(&Idx::load(images)?, &Idx::load(labels)?, classes)
+}
+
+#[cfg(test)]
+fn fixture(code: u8, dims: &[u32], data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0, 0, code, dims.len() as u8];
+    dims.iter().for_each(|d| bytes.extend(d.to_be_bytes()));
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[test]
+fn test_idx_batch() {
+    // two 2x3 images and their labels
+    let images = fixture(
+        0x08,
+        &[2, 2, 3],
+        &[0, 51, 255, 0, 0, 0, 255, 255, 0, 0, 102, 0],
+    );
+    let labels = fixture(0x08, &[2], &[2, 0]);
+    let images = Idx::read_from(&images[..]).unwrap();
+    let labels = Idx::read_from(&labels[..]).unwrap();
+    assert_eq!(images.dims, vec![2, 2, 3]);
+    assert_eq!(images.sample_size(), 6);
+
+    let batch = idx_batch(&images, &labels, 3).unwrap();
+    assert_eq!(batch.input.get_row(0), &[0.0, 0.2, 1.0, 0.0, 0.0, 0.0]);
+    assert_eq!(batch.input.get_row(1), &[1.0, 1.0, 0.0, 0.0, 0.4, 0.0]);
+    assert_eq!(batch.expected.to_vec(), vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+    assert_eq!(batch.labels(), vec![2, 0]);
+
+    let error = idx_batch(&images, &labels, 2).unwrap_err();
+    assert_eq!(error.to_string(), "label 2 is not one of 2 classes");
+    let one = Idx::read_from(&fixture(0x08, &[1], &[0])[..]).unwrap();
+    assert!(idx_batch(&images, &one, 3).is_err());
+}
+
+#[test]
+fn test_idx_types() {
+    let mut data = Vec::new();
+    data.extend((-2i16).to_be_bytes());
+    data.extend(300i16.to_be_bytes());
+    let idx = Idx::read_from(&fixture(0x0B, &[2], &data)[..]).unwrap();
+    assert_eq!(idx.values, vec![-2.0, 300.0]);
+    assert_eq!(idx.to_mat().to_vec(), vec![-2.0, 300.0]);
+
+    let idx = Idx::read_from(&fixture(0x0D, &[1, 1], &1.5f32.to_be_bytes())[..]).unwrap();
+    assert_eq!(idx.values, vec![1.5]);
+
+    let truncated = fixture(0x08, &[2, 2], &[1, 2, 3]);
+    let error = Idx::read_from(&truncated[..]).unwrap_err();
+    assert_eq!(error.to_string(), "expected 4 bytes of idx data, found 3");
+    assert!(Idx::read_from(&[0x1f, 0x8b, 8, 0][..]).is_err());
+    assert!(Idx::read_from(&fixture(0x0A, &[1], &[0])[..]).is_err());
+
+    let huge = fixture(0x0E, &[0, u32::MAX, u32::MAX, u32::MAX], &[]);
+    let error = Idx::read_from(&huge[..]).unwrap_err();
+    assert_eq!(error.to_string(), "idx dimensions overflow");
+}
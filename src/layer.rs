@@ -0,0 +1,580 @@
+use crate::{
+    act::{Activation, ActivationFn},
+    mat::MatF64,
+    registry::Registry,
+    rng::Rng,
+    save::{self, Reader},
+};
+use std::{
+    any::Any,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Train,
+    Eval,
+}
+
+/// What a training forward pass keeps for `backward`, only the layer that
+/// produced it knows the concrete type.
+pub type Cache = Box<dyn Any + Send>;
+
+/// State a training pass changes through `&self`, like dropout generators or
+/// running statistics. Cloning copies the current value.
+#[derive(Default)]
+pub struct TrainState<T>(Mutex<T>);
+
+impl<T> TrainState<T> {
+    pub fn new(value: T) -> TrainState<T> {
+        TrainState(Mutex::new(value))
+    }
+
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.0.lock().unwrap())
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut().unwrap()
+    }
+}
+
+impl<T: Clone> TrainState<T> {
+    pub fn get(&self) -> T {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl<T: Clone> Clone for TrainState<T> {
+    fn clone(&self) -> Self {
+        TrainState::new(self.get())
+    }
+}
+
+pub trait Layer: Send + Sync {
+    /// Training forward pass over a batch (one sample per row), returns the
+    /// output and what `backward` needs.
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache);
+
+    /// Forward pass for predictions.
+    fn infer(&self, input: &MatF64) -> MatF64;
+
+    /// Takes the cache of a `forward` call and the gradient of its output,
+    /// returns the gradient of its input together with the batch summed
+    /// gradients of `params`.
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>);
+
+    /// Input and output width, `None` for layers that keep any width.
+    fn shape(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Trainable parameters, weights first then biases.
+    fn params(&self) -> Vec<&MatF64> {
+        Vec::new()
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        Vec::new()
+    }
+
+    /// Whether penalties and weight decay apply to `params`, norm layers opt
+    /// out since shrinking their gain collapses the normalization.
+    fn regularized(&self) -> bool {
+        true
+    }
+
+    fn set_mode(&mut self, _mode: Mode) {}
+
+    /// Reseeds any randomness the layer uses while training.
+    fn set_seed(&mut self, _seed: u64) {}
+
+    /// Current state of the layer's generator, `set_seed` with it continues
+    /// the exact same sequence.
+    fn rng_state(&self) -> Option<u64> {
+        None
+    }
+
+    /// Single word tag written in front of the layer when saving.
+    fn kind(&self) -> &str;
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()>;
+
+    fn boxed_clone(&self) -> Box<dyn Layer>;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl Clone for Box<dyn Layer> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Sequential {
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Sequential {
+    pub fn new() -> Sequential {
+        Sequential { layers: Vec::new() }
+    }
+
+    pub fn with(mut self, layer: impl Layer + 'static) -> Sequential {
+        self.push(layer);
+        self
+    }
+
+    pub fn push(&mut self, layer: impl Layer + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn insert(&mut self, index: usize, layer: impl Layer + 'static) {
+        self.layers.insert(index, Box::new(layer));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Box<dyn Layer> {
+        self.layers.remove(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+
+    pub fn layers_mut(&mut self) -> &mut [Box<dyn Layer>] {
+        &mut self.layers
+    }
+
+    /// Training forward pass, returns the output and the cache of every layer.
+    pub fn forward(&self, input: &MatF64) -> (MatF64, Vec<Cache>) {
+        let mut caches = Vec::with_capacity(self.layers.len());
+        let output = self.layers.iter().fold(input.clone(), |x, layer| {
+            let (out, cache) = layer.forward(&x);
+            caches.push(cache);
+            out
+        });
+        (output, caches)
+    }
+
+    pub fn infer(&self, input: &MatF64) -> MatF64 {
+        self.layers
+            .iter()
+            .fold(input.clone(), |x, layer| layer.infer(&x))
+    }
+
+    pub fn activate(&self, input: &MatF64) -> Vec<MatF64> {
+        let mut output: Vec<MatF64> = Vec::with_capacity(self.layers.len() + 1);
+        output.push(input.clone());
+        for layer in self.layers.iter() {
+            let next = layer.infer(output.last().unwrap());
+            output.push(next);
+        }
+        output
+    }
+
+    /// Backpropagates `grad` through every layer using the caches of `forward`,
+    /// returns the parameter gradients per layer.
+    pub fn backward(&self, caches: &[Cache], grad: &MatF64) -> Vec<Vec<MatF64>> {
+        assert_eq!(caches.len(), self.layers.len(), "one cache per layer");
+        let mut current = grad.clone();
+        let mut out: Vec<Vec<MatF64>> = Vec::with_capacity(self.layers.len());
+        for (layer, cache) in self.layers.iter().zip(caches).rev() {
+            let (prev, params) = layer.backward(cache, &current);
+            current = prev;
+            out.push(params);
+        }
+        out.reverse();
+        out
+    }
+
+    pub fn params(&self) -> Vec<&MatF64> {
+        self.layers.iter().flat_map(|l| l.params()).collect()
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.layers.iter_mut().for_each(|l| l.set_mode(mode));
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        let mut rng = Rng::new(seed);
+        self.layers
+            .iter_mut()
+            .for_each(|l| l.set_seed(rand::RngCore::next_u64(&mut rng)));
+    }
+
+    /// Generator states of the layers that have one, in layer order.
+    pub fn rng_states(&self) -> Vec<u64> {
+        self.layers.iter().filter_map(|l| l.rng_state()).collect()
+    }
+
+    pub fn set_rng_states(&mut self, states: &[u64]) {
+        let mut layers = self.layers.iter_mut().filter(|l| l.rng_state().is_some());
+        for state in states {
+            layers
+                .next()
+                .expect("more rng states than layers with a generator")
+                .set_seed(*state);
+        }
+        assert!(layers.next().is_none(), "missing rng states");
+    }
+
+    pub fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        self.layers.iter_mut().flat_map(|l| l.params_mut()).collect()
+    }
+
+    pub fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "layers {}", self.layers.len())?;
+        for layer in self.layers.iter() {
+            write!(w, "{} ", layer.kind())?;
+            layer.write_to(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut Reader, registry: &Registry) -> io::Result<Sequential> {
+        reader.expect("layers")?;
+        let count = reader.usize()?;
+        let mut layers = Vec::with_capacity(count);
+        let mut width = None;
+        for _ in 0..count {
+            let kind = reader.word()?;
+            let load = registry
+                .layer(&kind)
+                .ok_or_else(|| save::invalid(format!("unknown layer `{}`", kind)))?;
+            let layer = load(reader, registry)?;
+            if let Some((inputs, outputs)) = layer.shape() {
+                if width.is_some_and(|w| w != inputs) {
+                    return Err(save::invalid("layer shapes do not line up"));
+                }
+                width = Some(outputs);
+            }
+            layers.push(layer);
+        }
+        Ok(Sequential { layers })
+    }
+}
+
+#[derive(Clone)]
+pub struct Dense {
+    weights: MatF64,
+    biases: MatF64,
+    activation: Arc<dyn ActivationFn>,
+}
+
+// input and output of a training pass
+struct DenseCache {
+    input: MatF64,
+    output: MatF64,
+}
+
+impl Dense {
+    pub fn new(inputs: usize, outputs: usize, activation: impl ActivationFn + 'static) -> Dense {
+        Dense::from_parts(
+            MatF64::rand(inputs, outputs),
+            MatF64::zeros_row(outputs),
+            Arc::new(activation),
+        )
+    }
+
+    pub fn from_parts(weights: MatF64, biases: MatF64, activation: Arc<dyn ActivationFn>) -> Dense {
+        assert_eq!(biases.rows(), 1);
+        assert_eq!(biases.cols(), weights.cols());
+        Dense {
+            weights,
+            biases,
+            activation,
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.weights.rows()
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.weights.cols()
+    }
+
+    pub fn weights(&self) -> &MatF64 {
+        &self.weights
+    }
+
+    pub fn biases(&self) -> &MatF64 {
+        &self.biases
+    }
+
+    pub fn activation(&self) -> &dyn ActivationFn {
+        self.activation.as_ref()
+    }
+
+    pub fn set_activation(&mut self, activation: Arc<dyn ActivationFn>) {
+        self.activation = activation;
+    }
+
+    pub fn load(reader: &mut Reader, registry: &Registry) -> io::Result<Box<dyn Layer>> {
+        let name = reader.word()?;
+        let activation = registry
+            .activation(&name)
+            .ok_or_else(|| save::invalid(format!("unknown activation `{}`", name)))?;
+        let weights = reader.mat()?;
+        let biases = reader.mat()?;
+        if biases.rows() != 1 || biases.cols() != weights.cols() {
+            return Err(save::invalid("bias does not match weight shape"));
+        }
+        Ok(Box::new(Dense::from_parts(weights, biases, activation)))
+    }
+}
+
+impl Layer for Dense {
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        let output = self.infer(input);
+        let cache = DenseCache {
+            input: input.clone(),
+            output: output.clone(),
+        };
+        (output, Box::new(cache))
+    }
+
+    fn infer(&self, input: &MatF64) -> MatF64 {
+        assert_eq!(input.cols(), self.weights.rows());
+        let mut out = input.dot(&self.weights);
+        for row in out.iter_rows_mut() {
+            row.iter_mut()
+                .zip(self.biases.iter())
+                .for_each(|(v, b)| *v = self.activation.forward(*v + b));
+        }
+        out
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        let cache = cache
+            .downcast_ref::<DenseCache>()
+            .expect("not a dense cache");
+        let mut delta = cache.output.clone();
+        delta
+            .iter_mut()
+            .for_each(|x| *x = self.activation.derivative(*x));
+        delta *= grad;
+
+        let weight_grad = cache.input.transposed().dot(&delta);
+        let bias_grad = delta.sum_rows();
+        let input_grad = delta.dot(&self.weights.transposed());
+
+        (input_grad, vec![weight_grad, bias_grad])
+    }
+
+    fn shape(&self) -> Option<(usize, usize)> {
+        Some((self.inputs(), self.outputs()))
+    }
+
+    fn params(&self) -> Vec<&MatF64> {
+        vec![&self.weights, &self.biases]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        vec![&mut self.weights, &mut self.biases]
+    }
+
+    fn kind(&self) -> &str {
+        "dense"
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{}", self.activation.name())?;
+        save::write_mat(w, &self.weights)?;
+        save::write_mat(w, &self.biases)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct Dropout {
+    rate: f64,
+    mode: Mode,
+    rng: TrainState<Rng>,
+}
+
+impl Dropout {
+    pub fn new(rate: f64) -> Dropout {
+        assert!((0.0..1.0).contains(&rate), "dropout rate must be in [0, 1)");
+        Dropout {
+            rate,
+            mode: Mode::Train,
+            rng: TrainState::new(Rng::from_entropy()),
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        assert!((0.0..1.0).contains(&rate), "dropout rate must be in [0, 1)");
+        self.rate = rate;
+    }
+
+    pub fn load(reader: &mut Reader, _registry: &Registry) -> io::Result<Box<dyn Layer>> {
+        let rate = reader.f64()?;
+        if !(0.0..1.0).contains(&rate) {
+            return Err(save::invalid("dropout rate must be in [0, 1)"));
+        }
+        Ok(Box::new(Dropout::new(rate)))
+    }
+}
+
+impl Layer for Dropout {
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        if self.mode == Mode::Eval || self.rate == 0.0 {
+            return (input.clone(), Box::new(MatF64::empty(0, 0)));
+        }
+        let mask = self
+            .rng
+            .update(|rng| dropout_mask(self.rate, input.rows(), input.cols(), rng));
+        let mut out = input.clone();
+        out *= &mask;
+        (out, Box::new(mask))
+    }
+
+    // predictions and `Model::cost` stay deterministic, only training drops units
+    fn infer(&self, input: &MatF64) -> MatF64 {
+        input.clone()
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        let mask = cache.downcast_ref::<MatF64>().expect("not a dropout cache");
+        let mut out = grad.clone();
+        if !mask.is_empty() {
+            out *= mask;
+        }
+        (out, Vec::new())
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        *self.rng.get_mut() = Rng::new(seed);
+    }
+
+    fn rng_state(&self) -> Option<u64> {
+        Some(self.rng.get().state())
+    }
+
+    fn kind(&self) -> &str {
+        "dropout"
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{}", self.rate)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// inverted dropout, kept units are scaled up so eval needs no rescaling
+fn dropout_mask(rate: f64, rows: usize, cols: usize, rng: &mut Rng) -> MatF64 {
+    let scale = 1.0 / (1.0 - rate);
+    let mut mask = MatF64::zeros(rows, cols);
+    mask.iter_mut().for_each(|m| {
+        if rng.uniform() >= rate {
+            *m = scale;
+        }
+    });
+    mask
+}
+
+#[test]
+fn test_dense_backward() {
+    let dense = Dense::new(3, 2, Activation::Tanh);
+    let input = MatF64::rand(4, 3);
+    let (out, cache) = dense.forward(&input);
+    assert_eq!(out, dense.infer(&input));
+
+    let (input_grad, params) = dense.backward(&cache, &MatF64::rand(4, 2));
+    assert_eq!(input_grad.rows(), 4);
+    assert_eq!(input_grad.cols(), 3);
+    assert_eq!(params[0].rows(), 3);
+    assert_eq!(params[0].cols(), 2);
+    assert_eq!(params[1].cols(), 2);
+}
+
+#[test]
+fn test_sequential() {
+    let mut net = Sequential::new()
+        .with(Dense::new(2, 4, Activation::ReLU))
+        .with(Dense::new(4, 1, Activation::Sigmoid));
+
+    let input = MatF64::rand(5, 2);
+    let (out, caches) = net.forward(&input);
+    assert_eq!(out, net.infer(&input));
+    assert_eq!(net.activate(&input).len(), 3);
+    assert_eq!(net.params().len(), 4);
+
+    let grads = net.backward(&caches, &MatF64::rand(5, 1));
+    assert_eq!(grads.len(), 2);
+    assert_eq!(grads[0].len(), 2);
+
+    let mut saved = Vec::new();
+    net.push(Dense::new(2, 1, Activation::Sigmoid));
+    net.write_to(&mut saved).unwrap();
+    let mut reader = Reader::new(&saved[..]).unwrap();
+    assert!(Sequential::read_from(&mut reader, &Registry::default()).is_err());
+}
+
+#[test]
+fn test_dropout() {
+    let mut ones = MatF64::zeros(10, 20);
+    ones.iter_mut().for_each(|x| *x = 1.0);
+
+    let mut dropout = Dropout::new(0.5);
+    dropout.set_seed(7);
+    let (out, cache) = dropout.forward(&ones);
+    assert!(out.iter().all(|x| *x == 0.0 || *x == 2.0));
+    assert!(out.iter().any(|x| *x == 0.0));
+
+    let (grad, _) = dropout.backward(&cache, &ones);
+    assert_eq!(grad, out);
+
+    let mut again = Dropout::new(0.5);
+    again.set_seed(7);
+    assert_eq!(again.forward(&ones).0, out);
+    assert_eq!(again.infer(&ones), ones);
+
+    dropout.set_mode(Mode::Eval);
+    assert_eq!(dropout.forward(&ones).0, ones);
+    assert_eq!(dropout.infer(&ones), ones);
+}
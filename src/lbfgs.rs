@@ -0,0 +1,342 @@
+use crate::{batch::TrainingBatch, layer::Mode, nn::Model};
+use std::collections::VecDeque;
+
+/// Full batch L-BFGS with a strong Wolfe line search.
+///
+/// Minimizes `Model::cost / 2`, whose gradient is exactly `Model::gradient`.
+/// The model is switched to eval mode while optimizing so the objective is
+/// deterministic, the previous mode is restored afterwards.
+#[derive(Clone, Debug)]
+pub struct Lbfgs {
+    pub history: usize,
+    pub max_iter: usize,
+    pub tolerance: f64,
+    pub c1: f64,
+    pub c2: f64,
+    pub max_line_search: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LbfgsReport {
+    pub iterations: usize,
+    pub evaluations: usize,
+    pub cost: f64,
+    pub gradient_norm: f64,
+    pub converged: bool,
+}
+
+impl Default for Lbfgs {
+    fn default() -> Self {
+        Lbfgs {
+            history: 10,
+            max_iter: 100,
+            tolerance: 1e-6,
+            c1: 1e-4,
+            c2: 0.9,
+            max_line_search: 25,
+        }
+    }
+}
+
+struct Oracle<'a> {
+    model: &'a mut Model,
+    batch: &'a TrainingBatch,
+    evaluations: usize,
+}
+
+impl Oracle<'_> {
+    fn eval(&mut self, x: &[f64]) -> (f64, Vec<f64>) {
+        self.evaluations += 1;
+        self.model.set_flat_params(x);
+        // one forward pass, in eval mode its cost equals `Model::cost`
+        let (cost, gradients) = self.model.cost_and_gradient(self.batch);
+        (cost / 2.0, gradients.to_vec())
+    }
+}
+
+struct Point {
+    alpha: f64,
+    f: f64,
+    dg: f64,
+}
+
+impl Lbfgs {
+    pub fn new() -> Lbfgs {
+        Lbfgs::default()
+    }
+
+    pub fn with_history(mut self, history: usize) -> Lbfgs {
+        assert!(history > 0);
+        self.history = history;
+        self
+    }
+
+    pub fn with_max_iter(mut self, max_iter: usize) -> Lbfgs {
+        self.max_iter = max_iter;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Lbfgs {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn minimize(&self, model: &mut Model, batch: &TrainingBatch) -> LbfgsReport {
+        let mode = model.mode();
+        model.set_mode(Mode::Eval);
+
+        let mut oracle = Oracle {
+            model,
+            batch,
+            evaluations: 0,
+        };
+
+        let mut x = oracle.model.flat_params();
+        let (mut f, mut g) = oracle.eval(&x);
+        let mut history: VecDeque<(Vec<f64>, Vec<f64>, f64)> = VecDeque::new();
+        let mut iterations = 0;
+        let mut converged = norm(&g) <= self.tolerance;
+
+        while !converged && iterations < self.max_iter {
+            let mut d = self.direction(&g, &history);
+            if dot(&d, &g) >= 0.0 {
+                // lost descent, start over from steepest descent
+                history.clear();
+                d = g.iter().map(|v| -v).collect();
+            }
+
+            let initial = if history.is_empty() {
+                (1.0 / norm(&g)).min(1.0)
+            } else {
+                1.0
+            };
+
+            let Some((alpha, f_new, g_new)) = self.line_search(&mut oracle, &x, f, &g, &d, initial)
+            else {
+                break;
+            };
+
+            let s: Vec<f64> = d.iter().map(|v| alpha * v).collect();
+            let y: Vec<f64> = g_new.iter().zip(g.iter()).map(|(a, b)| a - b).collect();
+            x.iter_mut().zip(s.iter()).for_each(|(a, b)| *a += b);
+
+            let sy = dot(&s, &y);
+            if sy > 1e-12 {
+                if history.len() == self.history {
+                    history.pop_front();
+                }
+                history.push_back((s, y, 1.0 / sy));
+            }
+
+            let improvement = f - f_new;
+            f = f_new;
+            g = g_new;
+            iterations += 1;
+            converged = norm(&g) <= self.tolerance;
+            if improvement.abs() <= f64::EPSILON * f.abs().max(1.0) {
+                break;
+            }
+        }
+
+        oracle.model.set_flat_params(&x);
+        let evaluations = oracle.evaluations;
+        oracle.model.set_mode(mode);
+
+        LbfgsReport {
+            iterations,
+            evaluations,
+            cost: 2.0 * f,
+            gradient_norm: norm(&g),
+            converged,
+        }
+    }
+
+    // two loop recursion
+    fn direction(&self, g: &[f64], history: &VecDeque<(Vec<f64>, Vec<f64>, f64)>) -> Vec<f64> {
+        let mut q: Vec<f64> = g.to_vec();
+        let mut alphas = Vec::with_capacity(history.len());
+        for (s, y, rho) in history.iter().rev() {
+            let a = rho * dot(s, &q);
+            q.iter_mut().zip(y.iter()).for_each(|(q, y)| *q -= a * y);
+            alphas.push(a);
+        }
+
+        if let Some((s, y, _)) = history.back() {
+            let gamma = dot(s, y) / dot(y, y);
+            q.iter_mut().for_each(|v| *v *= gamma);
+        }
+
+        for ((s, y, rho), a) in history.iter().zip(alphas.iter().rev()) {
+            let b = rho * dot(y, &q);
+            q.iter_mut()
+                .zip(s.iter())
+                .for_each(|(q, s)| *q += (a - b) * s);
+        }
+
+        q.iter_mut().for_each(|v| *v = -*v);
+        q
+    }
+
+    // Nocedal & Wright, algorithm 3.5
+    fn line_search(
+        &self,
+        oracle: &mut Oracle,
+        x: &[f64],
+        f0: f64,
+        g0: &[f64],
+        d: &[f64],
+        initial: f64,
+    ) -> Option<(f64, f64, Vec<f64>)> {
+        let dg0 = dot(g0, d);
+        let mut prev = Point {
+            alpha: 0.0,
+            f: f0,
+            dg: dg0,
+        };
+        let mut alpha = initial;
+
+        for i in 0..self.max_line_search {
+            let (f, g) = oracle.eval(&step(x, d, alpha));
+            let dg = dot(&g, d);
+            let current = Point { alpha, f, dg };
+
+            if f > f0 + self.c1 * alpha * dg0 || (i > 0 && f >= prev.f) {
+                return self.zoom(oracle, x, f0, dg0, d, prev, current);
+            }
+            if dg.abs() <= -self.c2 * dg0 {
+                return Some((alpha, f, g));
+            }
+            if dg >= 0.0 {
+                return self.zoom(oracle, x, f0, dg0, d, current, prev);
+            }
+
+            prev = current;
+            alpha *= 2.0;
+        }
+        None
+    }
+
+    // Nocedal & Wright, algorithm 3.6 with cubic interpolation
+    #[allow(clippy::too_many_arguments)]
+    fn zoom(
+        &self,
+        oracle: &mut Oracle,
+        x: &[f64],
+        f0: f64,
+        dg0: f64,
+        d: &[f64],
+        mut lo: Point,
+        mut hi: Point,
+    ) -> Option<(f64, f64, Vec<f64>)> {
+        for _ in 0..self.max_line_search {
+            let alpha = interpolate(&lo, &hi);
+            let (f, g) = oracle.eval(&step(x, d, alpha));
+            let dg = dot(&g, d);
+
+            if f > f0 + self.c1 * alpha * dg0 || f >= lo.f {
+                hi = Point { alpha, f, dg };
+            } else {
+                if dg.abs() <= -self.c2 * dg0 {
+                    return Some((alpha, f, g));
+                }
+                if dg * (hi.alpha - lo.alpha) >= 0.0 {
+                    hi = lo;
+                }
+                lo = Point { alpha, f, dg };
+            }
+
+            if (hi.alpha - lo.alpha).abs() < 1e-16 {
+                break;
+            }
+        }
+
+        // no strong Wolfe point found, still take the best decrease seen
+        if lo.alpha > 0.0 && lo.f < f0 {
+            let (f, g) = oracle.eval(&step(x, d, lo.alpha));
+            return Some((lo.alpha, f, g));
+        }
+        None
+    }
+}
+
+fn interpolate(a: &Point, b: &Point) -> f64 {
+    let (min, max) = if a.alpha < b.alpha {
+        (a.alpha, b.alpha)
+    } else {
+        (b.alpha, a.alpha)
+    };
+    let d1 = a.dg + b.dg - 3.0 * (a.f - b.f) / (a.alpha - b.alpha);
+    let radicand = d1 * d1 - a.dg * b.dg;
+    let bisect = 0.5 * (min + max);
+    if radicand < 0.0 {
+        return bisect;
+    }
+    let d2 = (b.alpha - a.alpha).signum() * radicand.sqrt();
+    let alpha = b.alpha - (b.alpha - a.alpha) * (b.dg + d2 - d1) / (b.dg - a.dg + 2.0 * d2);
+    // keep away from the interval ends, otherwise bisect
+    let margin = 0.1 * (max - min);
+    if alpha.is_finite() && alpha >= min + margin && alpha <= max - margin {
+        alpha
+    } else {
+        bisect
+    }
+}
+
+fn step(x: &[f64], d: &[f64], alpha: f64) -> Vec<f64> {
+    x.iter().zip(d.iter()).map(|(x, d)| x + alpha * d).collect()
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+fn norm(a: &[f64]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+#[test]
+fn test_lbfgs_regression() {
+    use crate::act::Activation;
+
+    let mut model = Model::new(&[1, 8, 1]);
+    model.set_layer_activation(0, Activation::Tanh);
+    let mut rng = crate::rng::Rng::new(4);
+    let params = (0..model.param_count())
+        .map(|_| rng.uniform() * 2.0 - 1.0)
+        .collect::<Vec<f64>>();
+    model.set_flat_params(&params);
+    let mut batch = TrainingBatch::empty(1, 1);
+    for i in 0..20 {
+        let x = i as f64 / 20.0;
+        batch.add(&[x], &[0.2 + 0.6 * x * x]);
+    }
+
+    let before = model.cost(&batch);
+    let report = Lbfgs::new().with_max_iter(200).minimize(&mut model, &batch);
+
+    assert!(report.cost < before * 1e-2);
+    assert!((report.cost - model.cost(&batch)).abs() < 1e-12);
+    assert!(report.evaluations >= report.iterations);
+    assert_eq!(model.mode(), Mode::Train);
+}
+
+#[test]
+fn test_lbfgs_converges() {
+    use crate::mat::MatF64;
+
+    // a single sigmoid unit can match targets from another one exactly
+    let teacher = Model::new(&[2, 1]);
+    let input = MatF64::rand(12, 2);
+    let mut batch = TrainingBatch::empty(2, 1);
+    for row in input.iter_rows() {
+        batch.add(row, &teacher.forward(row));
+    }
+
+    let mut model = Model::new(&[2, 1]);
+    let report = Lbfgs::new()
+        .with_tolerance(1e-8)
+        .with_max_iter(500)
+        .minimize(&mut model, &batch);
+    assert!(report.converged, "{:?}", report);
+    assert!(report.cost < 1e-10);
+}
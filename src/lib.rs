@@ -2,11 +2,59 @@
 pub mod mat;
 pub mod nn;
 pub mod act;
+pub mod augment;
+pub mod average;
 pub mod batch;
+pub mod checkpoint;
+pub mod csv;
+pub mod cv;
+pub mod dataset;
+pub mod encode;
+pub mod grad;
+pub mod idx;
+pub mod layer;
+pub mod lbfgs;
+pub mod loader;
+pub mod loss;
+pub mod norm;
+pub mod optim;
+pub mod pipeline;
+pub mod reg;
+pub mod registry;
+pub mod rng;
+pub mod save;
+pub mod scale;
+pub mod schedule;
+pub mod split;
+pub mod train;
+pub mod weight;
 
 pub mod prelude {
     pub use crate::nn::*;
     pub use crate::mat::*;
     pub use crate::act::*;
+    pub use crate::augment::*;
+    pub use crate::average::*;
     pub use crate::batch::*;
+    pub use crate::checkpoint::*;
+    pub use crate::csv::*;
+    pub use crate::cv::*;
+    pub use crate::dataset::*;
+    pub use crate::encode::*;
+    pub use crate::grad::*;
+    pub use crate::idx::*;
+    pub use crate::layer::*;
+    pub use crate::lbfgs::*;
+    pub use crate::loader::*;
+    pub use crate::loss::*;
+    pub use crate::norm::*;
+    pub use crate::optim::*;
+    pub use crate::pipeline::*;
+    pub use crate::reg::*;
+    pub use crate::registry::*;
+    pub use crate::scale::*;
+    pub use crate::schedule::*;
+    pub use crate::split::*;
+    pub use crate::train::*;
+    pub use crate::weight::*;
 }
@@ -0,0 +1,248 @@
+use crate::{augment::Augmenter, batch::TrainingBatch, dataset::Dataset, rng::Rng};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Builds upcoming mini-batches on worker threads while the current one trains.
+///
+/// Worker `w` assembles every batch `i` with `i % workers == w` and hands it
+/// over through its own bounded queue, read in turn, so the order matches
+/// `Dataset::shuffled` with `Rng::derive(seed, epoch)` no matter how many
+/// workers run.
+pub struct DataLoader<D> {
+    data: Arc<D>,
+    batch_size: usize,
+    workers: usize,
+    prefetch: usize,
+    seed: Option<u64>,
+    drop_last: bool,
+    augmentation: Option<Augmenter>,
+}
+
+// what the workers of one epoch share
+struct Plan {
+    order: Vec<usize>,
+    batch_size: usize,
+    total: usize,
+    workers: usize,
+    epoch: usize,
+    augmentation: Option<Augmenter>,
+}
+
+impl<D: Dataset + 'static> DataLoader<D> {
+    /// Two workers, each prefetching up to two batches, in dataset order.
+    pub fn new(data: D, batch_size: usize) -> DataLoader<D> {
+        DataLoader::from_arc(Arc::new(data), batch_size)
+    }
+
+    pub fn from_arc(data: Arc<D>, batch_size: usize) -> DataLoader<D> {
+        assert!(batch_size > 0);
+        DataLoader {
+            data,
+            batch_size,
+            workers: 2,
+            prefetch: 2,
+            seed: None,
+            drop_last: false,
+            augmentation: None,
+        }
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> DataLoader<D> {
+        assert!(workers > 0);
+        self.workers = workers;
+        self
+    }
+
+    /// Batches each worker may have ready before it waits.
+    pub fn with_prefetch(mut self, prefetch: usize) -> DataLoader<D> {
+        assert!(prefetch > 0);
+        self.prefetch = prefetch;
+        self
+    }
+
+    pub fn with_shuffle(mut self, seed: u64) -> DataLoader<D> {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_drop_last(mut self) -> DataLoader<D> {
+        self.drop_last = true;
+        self
+    }
+
+    /// Augments batch `i` of epoch `e` with key `Augmenter::key(e, len(), i)`.
+    pub fn with_augmentation(mut self, augmenter: Augmenter) -> DataLoader<D> {
+        self.augmentation = Some(augmenter);
+        self
+    }
+
+    pub fn data(&self) -> &Arc<D> {
+        &self.data
+    }
+
+    /// Batches per epoch.
+    pub fn len(&self) -> usize {
+        if self.drop_last {
+            self.data.len() / self.batch_size
+        } else {
+            self.data.len().div_ceil(self.batch_size)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Starts the workers for `epoch`, they stop when the iterator is dropped.
+    pub fn epoch(&self, epoch: usize) -> Prefetch {
+        let mut order = (0..self.data.len()).collect::<Vec<usize>>();
+        if let Some(seed) = self.seed {
+            Rng::derive(seed, epoch as u64).shuffle(&mut order);
+        }
+        let total = self.len();
+        let workers = self.workers.min(total);
+        let plan = Arc::new(Plan {
+            order,
+            batch_size: self.batch_size,
+            total,
+            workers,
+            epoch,
+            augmentation: self.augmentation.clone(),
+        });
+
+        let mut receivers = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for worker in 0..workers {
+            let (sender, receiver) = mpsc::sync_channel(self.prefetch);
+            let data = self.data.clone();
+            let plan = plan.clone();
+            handles.push(thread::spawn(move || work(&*data, &plan, worker, sender)));
+            receivers.push(receiver);
+        }
+
+        Prefetch {
+            receivers,
+            handles,
+            next: 0,
+            total,
+        }
+    }
+}
+
+fn work<D: Dataset + ?Sized>(
+    data: &D,
+    plan: &Plan,
+    worker: usize,
+    sender: SyncSender<TrainingBatch>,
+) {
+    for index in (worker..plan.total).step_by(plan.workers) {
+        let start = index * plan.batch_size;
+        let end = (start + plan.batch_size).min(plan.order.len());
+        let mut batch = data.batch(&plan.order[start..end]);
+        if let Some(augmenter) = &plan.augmentation {
+            augmenter.augment(&mut batch, Augmenter::key(plan.epoch, plan.total, index));
+        }
+        // the receiver is gone once the epoch iterator was dropped
+        if sender.send(batch).is_err() {
+            return;
+        }
+    }
+}
+
+/// One epoch of prefetched batches, see `DataLoader::epoch`.
+pub struct Prefetch {
+    receivers: Vec<Receiver<TrainingBatch>>,
+    handles: Vec<JoinHandle<()>>,
+    next: usize,
+    total: usize,
+}
+
+impl Iterator for Prefetch {
+    type Item = TrainingBatch;
+
+    fn next(&mut self) -> Option<TrainingBatch> {
+        if self.next == self.total {
+            return None;
+        }
+        let receiver = &self.receivers[self.next % self.receivers.len()];
+        let batch = receiver
+            .recv()
+            .expect("data loader worker panicked while building a batch");
+        self.next += 1;
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.total - self.next;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Prefetch {}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        // closing the queues makes blocked workers return
+        self.receivers.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[test]
+fn test_loader_order() {
+    use crate::mat::MatF64;
+
+    let batch = TrainingBatch::new(MatF64::rand(23, 2), MatF64::rand(23, 1));
+    for workers in [1, 3, 8] {
+        let loader = DataLoader::new(batch.clone(), 4)
+            .with_workers(workers)
+            .with_prefetch(1)
+            .with_shuffle(9);
+        assert_eq!(loader.len(), 6);
+        for epoch in 0..2 {
+            let expected = batch
+                .shuffled(4, &mut Rng::derive(9, epoch as u64))
+                .map(|b| b.input.to_vec())
+                .collect::<Vec<_>>();
+            let loaded = loader
+                .epoch(epoch)
+                .map(|b| b.input.to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(loaded, expected);
+        }
+    }
+
+    let loader = DataLoader::new(batch, 4).with_drop_last();
+    assert_eq!(loader.epoch(0).count(), 5);
+}
+
+#[test]
+fn test_loader_shutdown() {
+    use crate::dataset::FromFn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let fetched = Arc::new(AtomicUsize::new(0));
+    let counter = fetched.clone();
+    let data = FromFn::new(1000, 1, 1, move |i| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        (vec![i as f64], vec![0.0])
+    });
+    let loader = DataLoader::new(data, 10).with_workers(2).with_prefetch(2);
+
+    let mut epoch = loader.epoch(0);
+    assert_eq!(epoch.next().unwrap().input.to_vec()[0], 0.0);
+    drop(epoch);
+
+    // dropping joined the workers, at most the queued and in flight batches were built
+    let after_drop = fetched.load(Ordering::SeqCst);
+    assert!(after_drop <= 10 * (1 + 2 * 3), "{}", after_drop);
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert_eq!(fetched.load(Ordering::SeqCst), after_drop);
+}
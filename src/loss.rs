@@ -0,0 +1,111 @@
+use crate::mat::MatF64;
+use std::sync::Arc;
+
+pub trait LossFn: Send + Sync {
+    /// Loss summed over every output of the batch.
+    fn cost(&self, output: &MatF64, expected: &MatF64) -> f64;
+
+    /// Half the derivative of `cost` with respect to `output`, the scale
+    /// `Model::gradient` has always used for the squared error.
+    fn gradient(&self, output: &MatF64, expected: &MatF64) -> MatF64;
+
+    fn name(&self) -> &str;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Loss {
+    #[default]
+    Mse,
+    Mae,
+    BinaryCrossEntropy,
+}
+
+const CLAMP: f64 = 1e-12;
+
+impl LossFn for Loss {
+    fn cost(&self, output: &MatF64, expected: &MatF64) -> f64 {
+        assert_eq!(output.len(), expected.len());
+        output
+            .iter()
+            .zip(expected.iter())
+            .map(|(x, y)| match self {
+                Loss::Mse => (x - y) * (x - y),
+                Loss::Mae => (x - y).abs(),
+                Loss::BinaryCrossEntropy => {
+                    let p = x.clamp(CLAMP, 1.0 - CLAMP);
+                    -(y * p.ln() + (1.0 - y) * (1.0 - p).ln())
+                }
+            })
+            .sum()
+    }
+
+    fn gradient(&self, output: &MatF64, expected: &MatF64) -> MatF64 {
+        match self {
+            Loss::Mse => output - expected,
+            Loss::Mae => {
+                let mut out = output - expected;
+                out.iter_mut().for_each(|d| {
+                    *d = if *d > 0.0 {
+                        0.5
+                    } else if *d < 0.0 {
+                        -0.5
+                    } else {
+                        0.0
+                    }
+                });
+                out
+            }
+            Loss::BinaryCrossEntropy => {
+                let mut out = output.clone();
+                out.iter_mut().zip(expected.iter()).for_each(|(x, y)| {
+                    let p = x.clamp(CLAMP, 1.0 - CLAMP);
+                    *x = 0.5 * (p - y) / (p * (1.0 - p));
+                });
+                out
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Loss::Mse => "mse",
+            Loss::Mae => "mae",
+            Loss::BinaryCrossEntropy => "binary_cross_entropy",
+        }
+    }
+}
+
+impl<T: LossFn + ?Sized> LossFn for Arc<T> {
+    fn cost(&self, output: &MatF64, expected: &MatF64) -> f64 {
+        (**self).cost(output, expected)
+    }
+
+    fn gradient(&self, output: &MatF64, expected: &MatF64) -> MatF64 {
+        (**self).gradient(output, expected)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+#[test]
+fn test_loss_gradient() {
+    let output = crate::mat!((0.2, 0.7, 0.9));
+    let expected = crate::mat!((0.0, 1.0, 1.0));
+    let eps = 1e-7;
+
+    for loss in [Loss::Mse, Loss::Mae, Loss::BinaryCrossEntropy] {
+        let grad = loss.gradient(&output, &expected);
+        for i in 0..3 {
+            let mut plus = output.clone();
+            plus[(0, i)] += eps;
+            let numeric = (loss.cost(&plus, &expected) - loss.cost(&output, &expected)) / eps;
+            assert!(
+                (numeric - 2.0 * grad[(0, i)]).abs() < 1e-4,
+                "{}",
+                loss.name()
+            );
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use rayon::prelude::*;
 use std::ops;
 
 #[macro_export]
@@ -29,6 +30,8 @@ impl PartialEq for MatF64 {
     }
 }
 
+const PAR_DOT_ROWS: usize = 32;
+
 pub fn mat_dot(out: &mut MatF64, lhs: &MatF64, rhs: &MatF64) {
     assert_eq!(lhs.cols, rhs.rows);
     assert_eq!(out.rows, lhs.rows);
@@ -161,10 +164,41 @@ impl MatF64 {
     pub fn dot(&self, rhs: &MatF64) -> MatF64 {
         assert_eq!(self.cols, rhs.rows);
         let mut out = MatF64::zeros(self.rows, rhs.cols);
-        mat_dot(&mut out, self, rhs);
+        if self.rows < PAR_DOT_ROWS || rhs.cols == 0 {
+            mat_dot(&mut out, self, rhs);
+            return out;
+        }
+        out.data
+            .par_chunks_mut(rhs.cols)
+            .enumerate()
+            .for_each(|(r, row)| {
+                for (c, v) in row.iter_mut().enumerate() {
+                    for i in 0..self.cols {
+                        *v += self[(r, i)] * rhs[(i, c)];
+                    }
+                }
+            });
         out
     }
 
+    pub fn transposed(&self) -> MatF64 {
+        let mut out = self.clone();
+        out.transpose();
+        out
+    }
+
+    pub fn sum_rows(&self) -> MatF64 {
+        let mut out = MatF64::zeros_row(self.cols);
+        for row in self.iter_rows() {
+            out.data
+                .iter_mut()
+                .zip(row.iter())
+                .for_each(|(a, b)| *a += b);
+        }
+        out
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, other: &MatF64) -> MatF64 {
         assert_eq!(self.rows, other.rows);
         assert_eq!(self.cols, other.cols);
@@ -197,6 +231,10 @@ impl MatF64 {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &f64> {
         self.data.iter()
     }
@@ -207,6 +245,10 @@ impl MatF64 {
     pub fn iter_rows(&self) -> impl Iterator<Item = &[f64]> {
         self.data.chunks(self.cols)
     }
+
+    pub fn iter_rows_mut(&mut self) -> impl Iterator<Item = &mut [f64]> {
+        self.data.chunks_mut(self.cols)
+    }
 }
 
 impl ops::Index<(usize, usize)> for MatF64 {
@@ -346,3 +388,13 @@ fn test_dot() {
     mat_dot(&mut out, &m1, &m2);
     assert_eq!(out, expected);
 }
+
+#[test]
+fn test_par_dot() {
+    let m1 = MatF64::rand(100, 7);
+    let m2 = MatF64::rand(7, 5);
+    let mut expected = MatF64::zeros(100, 5);
+    mat_dot(&mut expected, &m1, &m2);
+    assert_eq!(m1.dot(&m2), expected);
+    assert_eq!(m1.sum_rows().cols(), 7);
+}
@@ -1,179 +1,440 @@
-use crate::{act::Activation, batch::TrainingBatch, mat::MatF64};
-use rayon::prelude::*;
-
+use crate::{
+    act::{Activation, ActivationFn},
+    batch::TrainingBatch,
+    grad::{Accumulator, Gradients},
+    layer::{Dense, Dropout, Layer, Mode, Sequential},
+    loss::{Loss, LossFn},
+    mat::MatF64,
+    norm::{BatchNorm, LayerNorm, RmsNorm},
+    reg::{Penalty, Regularization},
+    registry::Registry,
+    save::{self, Reader},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+#[derive(Clone)]
 pub struct Model {
-    weights: Vec<MatF64>,
-    biases: Vec<MatF64>,
-    activation: Activation,
+    net: Sequential,
+    mode: Mode,
+    seed: Option<u64>,
+    regularization: Regularization,
+    layer_regularization: Vec<Option<Regularization>>,
+    weight_decay: f64,
+    loss: Arc<dyn LossFn>,
+}
+
+impl From<Sequential> for Model {
+    fn from(net: Sequential) -> Self {
+        Model {
+            net,
+            mode: Mode::Train,
+            seed: None,
+            regularization: Regularization::default(),
+            layer_regularization: Vec::new(),
+            weight_decay: 0.0,
+            loss: Arc::new(Loss::Mse),
+        }
+    }
 }
 
 impl Model {
     pub fn new(arch: &[usize]) -> Model {
-        let mut weights: Vec<MatF64> = Vec::with_capacity(arch.len());
-        let mut biases: Vec<MatF64> = Vec::with_capacity(arch.len());
-
+        let mut net = Sequential::new();
         for i in 0..arch.len() - 1 {
-            weights.push(MatF64::rand(arch[i], arch[i + 1]));
-            biases.push(MatF64::zeros_row(arch[i + 1]));
+            net.push(Dense::new(arch[i], arch[i + 1], Activation::Sigmoid));
         }
+        Model::from(net)
+    }
 
-        Model {
-            weights,
-            biases,
-            activation: Activation::Sigmoid,
-        }
+    pub fn net(&self) -> &Sequential {
+        &self.net
     }
 
-    pub fn set_activation(&mut self, activation: Activation) {
-        self.activation = activation;
+    pub fn net_mut(&mut self) -> &mut Sequential {
+        &mut self.net
     }
 
-    pub fn forward(&self, input: &[f64]) -> Vec<f64> {
-        let output = self.activate(&MatF64::row_from_slice(input));
-        output.last().unwrap().to_vec()
+    pub fn mode(&self) -> Mode {
+        self.mode
     }
 
-    pub fn activate(&self, input: &MatF64) -> Vec<MatF64> {
-        assert_eq!(input.len(), self.weights[0].rows());
-
-        let mut output: Vec<MatF64> = Vec::new();
-        output.push(input.clone());
-
-        for i in 0..self.weights.len() {
-            let mut next = output
-                .last()
-                .unwrap()
-                .dot(&self.weights[i])
-                .add(&self.biases[i]);
-            next.iter_mut()
-                .for_each(|v| *v = self.activation.forward(*v));
-            output.push(next);
-        }
-        output
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.net.set_mode(mode);
     }
 
-    pub fn cost(&self, batch: &TrainingBatch) -> f64 {
-        let mut cost = 0.0;
+    pub fn train(&mut self) {
+        self.set_mode(Mode::Train);
+    }
 
-        batch.iter().for_each(|(i, e)| {
-            let activation = self.activate(&MatF64::row_from_slice(i));
-            let output = activation.last().unwrap();
-            let expected = MatF64::row_from_slice(e);
+    pub fn eval(&mut self) {
+        self.set_mode(Mode::Eval);
+    }
 
-            cost += output
-                .iter()
-                .zip(expected.iter())
-                .map(|(x, y)| {
-                    let d = x - y;
-                    d * d
-                })
-                .sum::<f64>();
-        });
+    /// Makes dropout masks drawn by `gradient` reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.net.set_seed(seed);
+    }
 
-        cost / batch.len() as f64
+    // position of the `layer`-th dense layer inside the sequential net
+    fn dense_index(&self, layer: usize) -> usize {
+        self.net
+            .layers()
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.as_any().is::<Dense>())
+            .nth(layer)
+            .expect("dense layer index out of range")
+            .0
     }
 
-    pub fn gradient(&self, batch: &TrainingBatch) -> (Vec<MatF64>, Vec<MatF64>) {
-        let mut weight_gradient: Vec<MatF64> =
-            self.weights.iter().map(|m| MatF64::clone_zero(m)).collect();
+    fn sync_layers(&mut self) {
+        self.net.set_mode(self.mode);
+        if let Some(seed) = self.seed {
+            self.net.set_seed(seed);
+        }
+    }
 
-        let mut bias_gradient: Vec<MatF64> =
-            self.biases.iter().map(|m| MatF64::clone_zero(m)).collect();
+    /// Inserts `layer` directly behind the output of the dense layer `after`.
+    pub fn insert_after(&mut self, after: usize, layer: impl Layer + 'static) {
+        let index = self.dense_index(after);
+        self.net.insert(index + 1, layer);
+        self.sync_layers();
+    }
+
+    /// Adds batch normalization behind every hidden dense layer.
+    pub fn with_batch_norm(mut self) -> Model {
+        let hidden = self
+            .net
+            .layers()
+            .iter()
+            .filter_map(|l| l.as_any().downcast_ref::<Dense>())
+            .map(Dense::outputs)
+            .collect::<Vec<usize>>();
+        for (i, features) in hidden.iter().enumerate().take(hidden.len() - 1).rev() {
+            self.insert_after(i, BatchNorm::new(*features));
+        }
+        self
+    }
 
-        let o = batch
+    /// Sets the dropout applied to the output of the hidden dense layer `layer`,
+    /// a rate of 0.0 disables it again.
+    pub fn set_dropout(&mut self, layer: usize, rate: f64) {
+        let index = self.dense_index(layer);
+        let end = self.net.layers()[index + 1..]
             .iter()
-            .par_bridge()
-            .map(|(i, e)| {
-                let mut wout: Vec<MatF64> = Vec::new();
-                let mut bout: Vec<MatF64> = Vec::new();
+            .position(|l| l.as_any().is::<Dense>())
+            .map(|p| index + 1 + p)
+            .expect("dropout on the output layer is not supported");
+
+        match self.net.layers_mut()[index + 1..end]
+            .iter_mut()
+            .find_map(|l| l.as_any_mut().downcast_mut::<Dropout>())
+        {
+            Some(dropout) => dropout.set_rate(rate),
+            None => self.net.insert(end, Dropout::new(rate)),
+        }
+        self.sync_layers();
+    }
 
-                let input = MatF64::row_from_slice(i);
-                let expected = MatF64::row_from_slice(e);
+    /// Penalty applied to every regularized layer without its own setting,
+    /// see `Layer::regularized`.
+    pub fn set_regularization(&mut self, regularization: Regularization) {
+        self.regularization = regularization;
+    }
 
-                let activation = self.activate(&input);
-                let output = activation.last().unwrap().clone();
-                // output.iter_mut().for_each(|x| *x *= 2.0);
+    /// `layer` indexes the parametrized layers, in the order `gradient` returns them.
+    pub fn set_layer_regularization(&mut self, layer: usize, regularization: Regularization) {
+        if self.layer_regularization.len() <= layer {
+            self.layer_regularization.resize(layer + 1, None);
+        }
+        self.layer_regularization[layer] = Some(regularization);
+    }
+
+    pub fn layer_regularization(&self, layer: usize) -> Regularization {
+        self.layer_regularization
+            .get(layer)
+            .copied()
+            .flatten()
+            .unwrap_or(self.regularization)
+    }
+
+    /// Decoupled weight decay, weights shrink by `rate * decay` on every `learn`
+    /// independent of the gradient. Biases and norm layers are not decayed.
+    pub fn set_weight_decay(&mut self, decay: f64) {
+        assert!(decay >= 0.0);
+        self.weight_decay = decay;
+    }
 
-                let error = &output - &expected;
+    pub fn penalty(&self) -> f64 {
+        self.net
+            .params()
+            .chunks(2)
+            .zip(self.regularized())
+            .enumerate()
+            .filter(|(_, (_, regularized))| *regularized)
+            .map(|(i, (pair, _))| {
+                let reg = self.layer_regularization(i);
+                reg.weights.cost(pair[0]) + reg.biases.cost(pair[1])
+            })
+            .sum()
+    }
 
-                let mut current_error = error;
+    // `Layer::regularized` of every parametrized layer, in `gradient` order
+    fn regularized(&self) -> Vec<bool> {
+        self.net
+            .layers()
+            .iter()
+            .filter(|l| !l.params().is_empty())
+            .map(|l| l.regularized())
+            .collect()
+    }
 
-                for l in (1..activation.len()).rev() {
-                    let mut delta = activation[l].clone();
+    fn dense_layers_mut(&mut self) -> impl Iterator<Item = &mut Dense> {
+        self.net
+            .layers_mut()
+            .iter_mut()
+            .filter_map(|l| l.as_any_mut().downcast_mut::<Dense>())
+    }
 
-                    delta
-                        .iter_mut()
-                        .for_each(|x| *x = self.activation.derivative(*x));
+    pub fn set_activation(&mut self, activation: impl ActivationFn + 'static) {
+        let activation: Arc<dyn ActivationFn> = Arc::new(activation);
+        self.dense_layers_mut()
+            .for_each(|d| d.set_activation(activation.clone()));
+    }
 
-                    delta *= current_error;
+    /// `layer` counts dense layers only, in the order given to `Model::new`.
+    pub fn set_layer_activation(&mut self, layer: usize, activation: impl ActivationFn + 'static) {
+        let dense = self
+            .dense_layers_mut()
+            .nth(layer)
+            .expect("dense layer index out of range");
+        dense.set_activation(Arc::new(activation));
+    }
 
-                    let mut prev_weights = self.weights[l - 1].clone();
-                    prev_weights.transpose();
+    pub fn layer_activation(&self, layer: usize) -> &dyn ActivationFn {
+        self.net
+            .layers()
+            .iter()
+            .filter_map(|l| l.as_any().downcast_ref::<Dense>())
+            .nth(layer)
+            .expect("dense layer index out of range")
+            .activation()
+    }
 
-                    let prev_error = delta.dot(&prev_weights);
+    /// Prediction for one sample, always as in eval mode. `Mode` only changes
+    /// the training pass of `gradient`, e.g. whether dropout drops units.
+    pub fn forward(&self, input: &[f64]) -> Vec<f64> {
+        self.net.infer(&MatF64::row_from_slice(input)).to_vec()
+    }
 
-                    let mut prev_activation = activation[l - 1].clone();
-                    prev_activation.transpose();
+    pub fn activate(&self, input: &MatF64) -> Vec<MatF64> {
+        self.net.activate(input)
+    }
 
-                    let wdelta = prev_activation.dot(&delta);
+    pub fn loss(&self) -> &dyn LossFn {
+        self.loss.as_ref()
+    }
 
-                    current_error = prev_error;
+    pub fn set_loss(&mut self, loss: impl LossFn + 'static) {
+        self.loss = Arc::new(loss);
+    }
 
-                    wout.push(wdelta);
-                    bout.push(delta);
-                }
+    /// Mean loss per output plus the penalty, samples count by their weight.
+    pub fn cost(&self, batch: &TrainingBatch) -> f64 {
+        let output = self.net.infer(&batch.input);
+        let cost = self.summed_cost(&output, batch);
 
-                wout.reverse();
-                bout.reverse();
+        cost / batch.len() as f64 + self.penalty()
+    }
 
-                (wout, bout)
+    fn summed_cost(&self, output: &MatF64, batch: &TrainingBatch) -> f64 {
+        let Some(factors) = sample_factors(batch) else {
+            return self.loss.cost(output, &batch.expected);
+        };
+        output
+            .iter_rows()
+            .zip(batch.expected.iter_rows())
+            .zip(factors)
+            .filter(|(_, factor)| *factor > 0.0)
+            .map(|((o, e), factor)| {
+                let cost = self
+                    .loss
+                    .cost(&MatF64::row_from_slice(o), &MatF64::row_from_slice(e));
+                cost * factor
             })
-            .collect::<Vec<(Vec<MatF64>, Vec<MatF64>)>>();
+            .sum()
+    }
 
-        // --- sum gradient ---
-        o.iter().for_each(|(w, b)| {
-            weight_gradient
-                .iter_mut()
-                .zip(w.iter())
-                .for_each(|(a, b)| *a += b);
-            bias_gradient
-                .iter_mut()
-                .zip(b.iter())
-                .for_each(|(a, b)| *a += b);
-        });
+    pub fn param_count(&self) -> usize {
+        self.net.params().iter().map(|m| m.len()).sum()
+    }
 
-        // --- avarage gradient ---
-        for i in 0..self.weights.len() {
-            weight_gradient[i]
-                .iter_mut()
-                .for_each(|x| *x /= batch.len() as f64);
-            bias_gradient[i]
+    /// Every parameter in one vector, ordered like `Gradients::to_vec`.
+    pub fn flat_params(&self) -> Vec<f64> {
+        self.net
+            .params()
+            .iter()
+            .flat_map(|m| m.iter().copied())
+            .collect()
+    }
+
+    pub fn set_flat_params(&mut self, flat: &[f64]) {
+        assert_eq!(flat.len(), self.param_count());
+        let mut values = flat.iter();
+        for m in self.net.params_mut() {
+            m.iter_mut().for_each(|x| *x = *values.next().unwrap());
+        }
+    }
+
+    pub fn zero_gradients(&self) -> Gradients {
+        let params = self.net.params();
+        Gradients::new(
+            params.iter().step_by(2).map(|m| MatF64::clone_zero(m)).collect(),
+            params.iter().skip(1).step_by(2).map(|m| MatF64::clone_zero(m)).collect(),
+        )
+    }
+
+    /// Returns the averaged gradient of every parametrized layer, split into
+    /// weights and biases (each such layer exposes exactly one of both).
+    pub fn gradient(&self, batch: &TrainingBatch) -> Gradients {
+        self.cost_and_gradient(batch).1
+    }
+
+    /// Like `gradient`, also returns the cost of the training forward pass.
+    pub fn cost_and_gradient(&self, batch: &TrainingBatch) -> (f64, Gradients) {
+        let (output, caches) = self.net.forward(&batch.input);
+        let cost = self.summed_cost(&output, batch) / batch.len() as f64 + self.penalty();
+        let mut error = self.loss.gradient(&output, &batch.expected);
+        if let Some(factors) = sample_factors(batch) {
+            for (row, factor) in error.iter_rows_mut().zip(factors) {
+                row.iter_mut().for_each(|d| *d *= factor);
+            }
+        }
+
+        let mut weight_gradient: Vec<MatF64> = Vec::new();
+        let mut bias_gradient: Vec<MatF64> = Vec::new();
+
+        for mut params in self.net.backward(&caches, &error) {
+            if params.is_empty() {
+                continue;
+            }
+            assert_eq!(params.len(), 2, "layer must expose a weight and a bias");
+            params
                 .iter_mut()
-                .for_each(|x| *x /= batch.len() as f64);
+                .for_each(|m| m.iter_mut().for_each(|x| *x /= batch.len() as f64));
+            bias_gradient.push(params.pop().unwrap());
+            weight_gradient.push(params.pop().unwrap());
         }
 
-        (weight_gradient, bias_gradient)
+        let regularized = self.regularized();
+        for (i, pair) in self.net.params().chunks(2).enumerate() {
+            if !regularized[i] {
+                continue;
+            }
+            let reg = self.layer_regularization(i);
+            reg.weights.add_gradient(pair[0], &mut weight_gradient[i]);
+            reg.biases.add_gradient(pair[1], &mut bias_gradient[i]);
+        }
+
+        (cost, Gradients::new(weight_gradient, bias_gradient))
     }
 
-    pub fn learn(&mut self, weight_gradiant: Vec<MatF64>, bias_gradiant: Vec<MatF64>, rate: f64) {
-        assert_eq!(weight_gradiant.len(), self.weights.len());
-        assert_eq!(bias_gradiant.len(), self.biases.len());
+    pub fn learn(&mut self, gradients: &Gradients, rate: f64) {
+        let regularized = self.regularized();
+        let mut params = self.net.params_mut();
+        assert_eq!(gradients.layers() * 2, params.len());
+        let decay = 1.0 - rate * self.weight_decay;
+
+        for (i, pair) in params.chunks_mut(2).enumerate() {
+            assert_eq!(gradients.weights[i].len(), pair[0].len());
+            assert_eq!(gradients.biases[i].len(), pair[1].len());
 
-        for i in 0..self.weights.len() {
-            assert_eq!(weight_gradiant[i].len(), self.weights[i].len());
-            assert_eq!(bias_gradiant[i].len(), self.biases[i].len());
+            if self.weight_decay > 0.0 && regularized[i] {
+                pair[0].iter_mut().for_each(|w| *w *= decay);
+            }
 
-            self.weights[i]
+            pair[0]
                 .iter_mut()
-                .zip(weight_gradiant[i].iter())
+                .zip(gradients.weights[i].iter())
                 .for_each(|(a, b)| *a -= *b * rate);
 
-            self.biases[i]
+            pair[1]
                 .iter_mut()
-                .zip(bias_gradiant[i].iter())
+                .zip(gradients.biases[i].iter())
                 .for_each(|(a, b)| *a -= *b * rate);
         }
     }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>, registry: &Registry) -> io::Result<Model> {
+        Model::read_from(File::open(path)?, registry)
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "snail_nn 1")?;
+        self.net.write_to(w)
+    }
+
+    pub fn read_from(r: impl Read, registry: &Registry) -> io::Result<Model> {
+        Model::read(&mut Reader::new(r)?, registry)
+    }
+
+    /// Reads a model written by `write_to` from the middle of a larger file.
+    pub fn read(reader: &mut Reader, registry: &Registry) -> io::Result<Model> {
+        reader.expect("snail_nn")?;
+        reader.expect("1")?;
+        let net = Sequential::read_from(reader, registry)?;
+        if net.is_empty() {
+            return Err(save::invalid("model has no layers"));
+        }
+        Ok(Model::from(net))
+    }
+
+    /// Replaces the layers with saved ones of the same shape, keeping the
+    /// loss, regularization and mode, e.g. to resume from a checkpoint.
+    pub fn read_layers(&mut self, reader: &mut Reader, registry: &Registry) -> io::Result<()> {
+        reader.expect("snail_nn")?;
+        reader.expect("1")?;
+        let net = Sequential::read_from(reader, registry)?;
+        let shapes = |net: &Sequential| {
+            net.params()
+                .iter()
+                .map(|m| (m.rows(), m.cols()))
+                .collect::<Vec<_>>()
+        };
+        if shapes(&net) != shapes(&self.net) {
+            return Err(save::invalid("saved layers do not match the model"));
+        }
+        self.net = net;
+        self.net.set_mode(self.mode);
+        Ok(())
+    }
+}
+
+// sample weights scaled to a mean of one, so uniform weights give the
+// unweighted cost up to rounding, the weighted path sums row by row
+fn sample_factors(batch: &TrainingBatch) -> Option<Vec<f64>> {
+    let weights = batch.weights()?;
+    let total = weights.iter().sum::<f64>();
+    assert!(total > 0.0, "sample weights sum to zero");
+    Some(
+        weights
+            .iter()
+            .map(|w| w * weights.len() as f64 / total)
+            .collect(),
+    )
 }
 
 #[test]
@@ -209,10 +470,253 @@ fn test_gradiant() {
     let expected = MatF64::random_rows(1);
 
     let train = TrainingBatch::new(input, expected);
-    let (w, b) = model.gradient(&train);
+    let grads = model.gradient(&train);
+
+    assert_eq!(grads.weights.len(), 3);
+    assert_eq!(grads.biases.len(), 3);
+
+    model.learn(&grads, 1.0);
+}
+
+struct Softsign;
+
+impl ActivationFn for Softsign {
+    fn forward(&self, x: f64) -> f64 {
+        x / (1.0 + x.abs())
+    }
+
+    fn derivative(&self, y: f64) -> f64 {
+        (1.0 - y.abs()).powi(2)
+    }
+
+    fn name(&self) -> &str {
+        "softsign"
+    }
+}
+
+#[test]
+fn test_custom_activation() {
+    let mut model = Model::new(&[2, 4, 1]);
+    model.set_layer_activation(0, Softsign);
+    model.set_layer_activation(1, Box::new(Activation::Tanh) as Box<dyn ActivationFn>);
+    assert_eq!(model.layer_activation(0).name(), "softsign");
+    assert_eq!(model.layer_activation(1).name(), "tanh");
+
+    let mut batch = TrainingBatch::empty(2, 1);
+    batch.add(&[0.0, 1.0], &[0.5]);
+    let grads = model.gradient(&batch);
+    model.learn(&grads, 0.1);
+}
+
+#[test]
+fn test_save_load() {
+    let mut model = Model::new(&[2, 3, 1]);
+    model.set_layer_activation(0, Softsign);
+
+    let mut buf: Vec<u8> = Vec::new();
+    model.write_to(&mut buf).unwrap();
+
+    assert!(Model::read_from(buf.as_slice(), &Registry::default()).is_err());
+
+    let mut registry = Registry::default();
+    registry.register_activation(Softsign);
+    let loaded = Model::read_from(buf.as_slice(), &registry).unwrap();
+
+    assert_eq!(loaded.layer_activation(0).name(), "softsign");
+    assert_eq!(loaded.forward(&[0.3, -0.7]), model.forward(&[0.3, -0.7]));
+}
+
+#[test]
+fn test_gradient_finite_difference() {
+    let mut model = Model::new(&[2, 3, 2]);
+    let batch = TrainingBatch::new(MatF64::rand(4, 2), MatF64::rand(4, 2));
+    let w = model.gradient(&batch).weights;
+
+    let eps = 1e-6;
+    let original = model.cost(&batch);
+    model.net_mut().params_mut()[0][(1, 2)] += eps;
+    let numeric = (model.cost(&batch) - original) / eps;
+
+    // cost is the plain squared error, the gradient drops the factor 2
+    assert!((numeric - 2.0 * w[0][(1, 2)]).abs() < 1e-4);
+}
+
+#[test]
+fn test_cross_entropy_gradient() {
+    let mut model = Model::new(&[2, 3, 1]);
+    model.set_loss(Loss::BinaryCrossEntropy);
+    let batch = TrainingBatch::new(MatF64::rand(4, 2), crate::mat!((0), (1), (1), (0)));
+    let (cost, gradients) = model.cost_and_gradient(&batch);
+    assert_eq!(cost, model.cost(&batch));
+
+    let eps = 1e-6;
+    model.net_mut().params_mut()[3][(0, 0)] += eps;
+    let numeric = (model.cost(&batch) - cost) / eps;
+    assert!((numeric - 2.0 * gradients.biases[1][(0, 0)]).abs() < 1e-4);
+}
+
+#[test]
+fn test_sample_weights() {
+    let model = Model::new(&[2, 3, 2]);
+    let batch = TrainingBatch::new(MatF64::rand(3, 2), MatF64::rand(3, 2));
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    let (cost, gradients) = model.cost_and_gradient(&batch.clone().with_weights(vec![1.0; 3]));
+    assert!(close(cost, model.cost(&batch)));
+    assert!(gradients
+        .to_vec()
+        .iter()
+        .zip(model.gradient(&batch).to_vec())
+        .all(|(a, b)| close(*a, b)));
+
+    // a weight of two counts like the sample twice, zero like leaving it out
+    let weighted = batch.clone().with_weights(vec![2.0, 1.0, 0.0]);
+    let repeated = batch.select(&[0, 0, 1]);
+    assert!(close(model.cost(&weighted), model.cost(&repeated)));
+    let (cost, gradients) = model.cost_and_gradient(&weighted);
+    assert!(close(cost, model.cost(&repeated)));
+    assert!(gradients
+        .to_vec()
+        .iter()
+        .zip(model.gradient(&repeated).to_vec())
+        .all(|(a, b)| close(*a, b)));
+}
+
+#[test]
+fn test_dropout_reproducible() {
+    let mut model = Model::new(&[2, 8, 8, 1]);
+    model.set_dropout(0, 0.5);
+    model.set_dropout(1, 0.2);
+    model.set_dropout(1, 0.3);
+    assert_eq!(model.net().len(), 5);
+
+    let batch = TrainingBatch::new(MatF64::rand(6, 2), MatF64::rand(6, 1));
+    let mut other = model.clone();
+
+    model.set_seed(3);
+    other.set_seed(3);
+    assert_eq!(model.gradient(&batch), other.gradient(&batch));
+    assert_eq!(model.gradient(&batch), other.gradient(&batch));
+
+    // eval mode trains as if there was no dropout
+    let mut plain = model.clone();
+    plain.set_dropout(0, 0.0);
+    plain.set_dropout(1, 0.0);
+    let train = model.gradient(&batch);
+    model.eval();
+    let eval = model.gradient(&batch);
+    assert_ne!(train, eval);
+    assert_eq!(eval, plain.gradient(&batch));
+}
 
-    assert_eq!(w.len(), 3);
-    assert_eq!(b.len(), 3);
+#[test]
+fn test_batch_norm_model() {
+    let mut model = Model::new(&[2, 6, 6, 1]).with_batch_norm();
+    model.set_dropout(0, 0.1);
+    assert_eq!(model.net().len(), 6);
+    assert!(model.net().layers()[1].as_any().is::<BatchNorm>());
+    assert!(model.net().layers()[2].as_any().is::<Dropout>());
+
+    let batch = TrainingBatch::new(MatF64::rand(8, 2), MatF64::rand(8, 1));
+    let grads = model.gradient(&batch);
+    assert_eq!(grads.layers(), 5);
+    model.learn(&grads, 0.5);
+
+    model.eval();
+    let mut buf: Vec<u8> = Vec::new();
+    model.write_to(&mut buf).unwrap();
+    let mut loaded = Model::read_from(buf.as_slice(), &Registry::default()).unwrap();
+    loaded.eval();
+    assert_eq!(loaded.forward(&[0.2, 0.9]), model.forward(&[0.2, 0.9]));
+}
+
+#[test]
+fn test_row_norm_model() {
+    let mut model = Model::new(&[3, 5, 4, 1]);
+    model.insert_after(0, LayerNorm::new(5));
+    model.insert_after(1, RmsNorm::new(4));
+    assert!(model.net().layers()[1].as_any().is::<LayerNorm>());
+    assert!(model.net().layers()[3].as_any().is::<RmsNorm>());
+
+    let mut batch = TrainingBatch::empty(3, 1);
+    batch.add(&[0.1, 0.5, 0.9], &[1.0]);
+    let before = model.cost(&batch);
+    for _ in 0..50 {
+        let grads = model.gradient(&batch);
+        model.learn(&grads, 0.5);
+    }
+    assert!(model.cost(&batch) < before);
+}
+
+#[test]
+fn test_regularization() {
+    let mut model = Model::new(&[2, 3, 1]);
+    let batch = TrainingBatch::new(MatF64::rand(4, 2), MatF64::rand(4, 1));
+    let plain = model.cost(&batch);
+
+    model.set_regularization(Regularization::weights(Penalty::elastic_net(0.1, 0.3)));
+    model.set_layer_regularization(1, Regularization::all(Penalty::l2(0.2)));
+    assert!(model.penalty() > 0.0);
+    assert_eq!(model.cost(&batch), plain + model.penalty());
+
+    // gradient stays half the derivative of the cost
+    let w = model.gradient(&batch).weights;
+    let eps = 1e-6;
+    let original = model.cost(&batch);
+    model.net_mut().params_mut()[0][(1, 2)] += eps;
+    let numeric = (model.cost(&batch) - original) / eps;
+    assert!((numeric - 2.0 * w[0][(1, 2)]).abs() < 1e-4);
+
+    model.set_weight_decay(0.5);
+    let before = model.net().params()[0].clone();
+    model.learn(&model.zero_gradients(), 0.1);
+    let after = model.net().params()[0];
+    before
+        .iter()
+        .zip(after.iter())
+        .for_each(|(x, y)| assert!((x * 0.95 - y).abs() < 1e-12));
+
+    // norm layers are neither penalized nor decayed
+    let mut normed = Model::new(&[2, 3, 1]);
+    normed.set_regularization(Regularization::all(Penalty::l2(0.2)));
+    normed.set_weight_decay(0.5);
+    let penalty = normed.penalty();
+    normed.insert_after(0, LayerNorm::new(3));
+    assert_eq!(normed.penalty(), penalty);
+    normed.learn(&normed.zero_gradients(), 0.1);
+    assert!(normed.net().params()[2].iter().all(|g| *g == 1.0));
+}
+
+#[test]
+fn test_clip_before_learn() {
+    let mut model = Model::new(&[2, 4, 1]);
+    model.set_activation(Activation::ReLU);
+    let batch = TrainingBatch::new(MatF64::rand(4, 2), MatF64::rand(4, 1));
+
+    let mut grads = model.gradient(&batch);
+    assert_eq!(grads.layer_norms().len(), 2);
+    grads.clip_norm(1e-3);
+    assert!(grads.norm() <= 1e-3 + 1e-12);
+
+    model.learn(&grads, 1.0);
+}
+
+#[test]
+fn test_accumulate_micro_batches() {
+    let mut model = Model::new(&[2, 3, 1]);
+    let batch = TrainingBatch::new(MatF64::rand(6, 2), MatF64::rand(6, 1));
+    let full = model.gradient(&batch);
+
+    let mut acc = Accumulator::new();
+    for start in [0, 2, 4] {
+        let mut chunk = TrainingBatch::empty(2, 1);
+        for i in start..start + 2 {
+            chunk.add(batch.input.get_row(i), batch.expected.get_row(i));
+        }
+        acc.add(&model.gradient(&chunk), chunk.len() as f64);
+    }
 
-    model.learn(w, b, 1.0);
+    let mean = acc.mean().unwrap();
+    let diff = &mean - &full;
+    assert!(diff.norm() < 1e-12);
+    model.learn(&mean, 0.1);
 }
@@ -0,0 +1,662 @@
+use crate::{
+    layer::{Cache, Layer, Mode, TrainState},
+    mat::MatF64,
+    registry::Registry,
+    save::{self, Reader},
+};
+use std::{
+    any::Any,
+    io::{self, Write},
+};
+
+#[derive(Clone)]
+pub struct BatchNorm {
+    gamma: MatF64,
+    beta: MatF64,
+    running: TrainState<Running>,
+    momentum: f64,
+    eps: f64,
+    mode: Mode,
+}
+
+#[derive(Clone)]
+struct Running {
+    mean: MatF64,
+    var: MatF64,
+}
+
+// what backward needs from a training pass
+struct BatchCache {
+    xhat: MatF64,
+    inv_std: Vec<f64>,
+    batch_stats: bool,
+}
+
+impl BatchNorm {
+    pub fn new(features: usize) -> BatchNorm {
+        let mut gamma = MatF64::zeros_row(features);
+        gamma.iter_mut().for_each(|g| *g = 1.0);
+        BatchNorm {
+            running: TrainState::new(Running {
+                mean: MatF64::zeros_row(features),
+                var: gamma.clone(),
+            }),
+            gamma,
+            beta: MatF64::zeros_row(features),
+            momentum: 0.1,
+            eps: 1e-5,
+            mode: Mode::Train,
+        }
+    }
+
+    pub fn with_momentum(mut self, momentum: f64) -> BatchNorm {
+        assert!((0.0..=1.0).contains(&momentum));
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> BatchNorm {
+        assert!(eps > 0.0);
+        self.eps = eps;
+        self
+    }
+
+    pub fn features(&self) -> usize {
+        self.gamma.cols()
+    }
+
+    pub fn running_mean(&self) -> MatF64 {
+        self.running.get().mean
+    }
+
+    pub fn running_var(&self) -> MatF64 {
+        self.running.get().var
+    }
+
+    fn batch_stats(input: &MatF64) -> (Vec<f64>, Vec<f64>) {
+        let n = input.rows() as f64;
+        let mean = input.sum_rows().iter().map(|s| s / n).collect::<Vec<f64>>();
+        let mut var = vec![0.0; input.cols()];
+        for row in input.iter_rows() {
+            for (j, x) in row.iter().enumerate() {
+                var[j] += (x - mean[j]).powi(2) / n;
+            }
+        }
+        (mean, var)
+    }
+
+    fn normalize(&self, input: &MatF64, mean: &[f64], inv_std: &[f64]) -> (MatF64, MatF64) {
+        let mut xhat = input.clone();
+        for row in xhat.iter_rows_mut() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (*x - mean[j]) * inv_std[j];
+            }
+        }
+        let mut out = xhat.clone();
+        for row in out.iter_rows_mut() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = *x * self.gamma[(0, j)] + self.beta[(0, j)];
+            }
+        }
+        (xhat, out)
+    }
+
+    fn inv_std(&self, var: &[f64]) -> Vec<f64> {
+        var.iter().map(|v| 1.0 / (v + self.eps).sqrt()).collect()
+    }
+
+    pub fn load(reader: &mut Reader, _registry: &Registry) -> io::Result<Box<dyn Layer>> {
+        let momentum = reader.f64()?;
+        let eps = reader.f64()?;
+        if !(0.0..=1.0).contains(&momentum) {
+            return Err(save::invalid("batch norm momentum must be in [0, 1]"));
+        }
+        if eps.is_nan() || eps <= 0.0 {
+            return Err(save::invalid("batch norm eps must be positive"));
+        }
+        let gamma = reader.mat()?;
+        let beta = reader.mat()?;
+        let running_mean = reader.mat()?;
+        let running_var = reader.mat()?;
+
+        let features = gamma.cols();
+        if [&gamma, &beta, &running_mean, &running_var]
+            .iter()
+            .any(|m| m.rows() != 1 || m.cols() != features)
+        {
+            return Err(save::invalid("batch norm parameters do not line up"));
+        }
+
+        let mut norm = BatchNorm::new(features);
+        norm.momentum = momentum;
+        norm.eps = eps;
+        norm.gamma = gamma;
+        norm.beta = beta;
+        *norm.running.get_mut() = Running {
+            mean: running_mean,
+            var: running_var,
+        };
+        Ok(Box::new(norm))
+    }
+}
+
+impl Layer for BatchNorm {
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        assert_eq!(input.cols(), self.features());
+        if self.mode == Mode::Eval || input.rows() == 0 {
+            let running = self.running.get();
+            let inv_std = self.inv_std(&running.var.to_vec());
+            let (xhat, out) = self.normalize(input, &running.mean.to_vec(), &inv_std);
+            let cache = BatchCache {
+                xhat,
+                inv_std,
+                batch_stats: false,
+            };
+            return (out, Box::new(cache));
+        }
+
+        let (mean, var) = BatchNorm::batch_stats(input);
+        let inv_std = self.inv_std(&var);
+        let (xhat, out) = self.normalize(input, &mean, &inv_std);
+
+        // running variance is tracked unbiased, like the usual frameworks do
+        let n = input.rows() as f64;
+        let correction = if n > 1.0 { n / (n - 1.0) } else { 1.0 };
+        self.running.update(|running| {
+            for j in 0..self.features() {
+                let m = self.momentum;
+                running.mean[(0, j)] = (1.0 - m) * running.mean[(0, j)] + m * mean[j];
+                running.var[(0, j)] = (1.0 - m) * running.var[(0, j)] + m * var[j] * correction;
+            }
+        });
+
+        let cache = BatchCache {
+            xhat,
+            inv_std,
+            batch_stats: true,
+        };
+        (out, Box::new(cache))
+    }
+
+    // batch statistics are only used by the training pass, so a single row
+    // is not normalized to `beta`
+    fn infer(&self, input: &MatF64) -> MatF64 {
+        assert_eq!(input.cols(), self.features());
+        let running = self.running.get();
+        let inv_std = self.inv_std(&running.var.to_vec());
+        self.normalize(input, &running.mean.to_vec(), &inv_std).1
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        let cache = cache
+            .downcast_ref::<BatchCache>()
+            .expect("not a batch norm cache");
+        let features = self.features();
+        let mut dgamma = MatF64::zeros_row(features);
+        let mut dbeta = MatF64::zeros_row(features);
+
+        let n = grad.rows() as f64;
+        let mut sum_dxhat = vec![0.0; features];
+        let mut sum_dxhat_xhat = vec![0.0; features];
+        for (g, x) in grad.iter_rows().zip(cache.xhat.iter_rows()) {
+            for j in 0..features {
+                dgamma[(0, j)] += g[j] * x[j];
+                dbeta[(0, j)] += g[j];
+                let dxhat = g[j] * self.gamma[(0, j)];
+                sum_dxhat[j] += dxhat;
+                sum_dxhat_xhat[j] += dxhat * x[j];
+            }
+        }
+
+        let mut input_grad = MatF64::zeros(grad.rows(), features);
+        for r in 0..grad.rows() {
+            for j in 0..features {
+                let dxhat = grad[(r, j)] * self.gamma[(0, j)];
+                input_grad[(r, j)] = if cache.batch_stats {
+                    cache.inv_std[j] / n
+                        * (n * dxhat - sum_dxhat[j] - cache.xhat[(r, j)] * sum_dxhat_xhat[j])
+                } else {
+                    // running statistics are constants
+                    dxhat * cache.inv_std[j]
+                };
+            }
+        }
+
+        (input_grad, vec![dgamma, dbeta])
+    }
+
+    fn shape(&self) -> Option<(usize, usize)> {
+        Some((self.features(), self.features()))
+    }
+
+    fn params(&self) -> Vec<&MatF64> {
+        vec![&self.gamma, &self.beta]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        vec![&mut self.gamma, &mut self.beta]
+    }
+
+    fn regularized(&self) -> bool {
+        false
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn kind(&self) -> &str {
+        "batch_norm"
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{} {}", self.momentum, self.eps)?;
+        save::write_mat(w, &self.gamma)?;
+        save::write_mat(w, &self.beta)?;
+        let running = self.running.get();
+        save::write_mat(w, &running.mean)?;
+        save::write_mat(w, &running.var)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// per sample normalization shared by `LayerNorm` and `RmsNorm`
+#[derive(Clone)]
+struct RowNorm {
+    gain: MatF64,
+    bias: MatF64,
+    eps: f64,
+    center: bool,
+}
+
+// normalized rows and their inverse scale of a training pass
+struct RowCache {
+    xhat: MatF64,
+    inv_scale: Vec<f64>,
+}
+
+impl RowNorm {
+    fn new(features: usize, center: bool) -> RowNorm {
+        let mut gain = MatF64::zeros_row(features);
+        gain.iter_mut().for_each(|g| *g = 1.0);
+        RowNorm {
+            gain,
+            bias: MatF64::zeros_row(features),
+            eps: 1e-5,
+            center,
+        }
+    }
+
+    fn normalize(&self, input: &MatF64) -> (MatF64, Vec<f64>, MatF64) {
+        assert_eq!(input.cols(), self.gain.cols());
+        let d = input.cols() as f64;
+        let mut xhat = input.clone();
+        let mut inv_scale = Vec::with_capacity(input.rows());
+        for row in xhat.iter_rows_mut() {
+            let mean = if self.center {
+                row.iter().sum::<f64>() / d
+            } else {
+                0.0
+            };
+            let var = row.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / d;
+            let inv = 1.0 / (var + self.eps).sqrt();
+            row.iter_mut().for_each(|x| *x = (*x - mean) * inv);
+            inv_scale.push(inv);
+        }
+        let mut out = xhat.clone();
+        for row in out.iter_rows_mut() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = *x * self.gain[(0, j)] + self.bias[(0, j)];
+            }
+        }
+        (xhat, inv_scale, out)
+    }
+
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        let (xhat, inv_scale, out) = self.normalize(input);
+        (out, Box::new(RowCache { xhat, inv_scale }))
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        let cache = cache
+            .downcast_ref::<RowCache>()
+            .expect("not a row norm cache");
+        let features = self.gain.cols();
+        let d = features as f64;
+        let mut dgain = MatF64::zeros_row(features);
+        let mut dbias = MatF64::zeros_row(features);
+        let mut input_grad = MatF64::zeros(grad.rows(), features);
+
+        for r in 0..grad.rows() {
+            let mut mean_dxhat = 0.0;
+            let mut mean_dxhat_xhat = 0.0;
+            for j in 0..features {
+                let dxhat = grad[(r, j)] * self.gain[(0, j)];
+                mean_dxhat += dxhat / d;
+                mean_dxhat_xhat += dxhat * cache.xhat[(r, j)] / d;
+                dgain[(0, j)] += grad[(r, j)] * cache.xhat[(r, j)];
+                dbias[(0, j)] += grad[(r, j)];
+            }
+            if !self.center {
+                mean_dxhat = 0.0;
+            }
+            for j in 0..features {
+                let dxhat = grad[(r, j)] * self.gain[(0, j)];
+                input_grad[(r, j)] = cache.inv_scale[r]
+                    * (dxhat - mean_dxhat - cache.xhat[(r, j)] * mean_dxhat_xhat);
+            }
+        }
+
+        (input_grad, vec![dgain, dbias])
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{}", self.eps)?;
+        save::write_mat(w, &self.gain)?;
+        save::write_mat(w, &self.bias)
+    }
+
+    fn load(reader: &mut Reader, center: bool) -> io::Result<RowNorm> {
+        let eps = reader.f64()?;
+        let gain = reader.mat()?;
+        let bias = reader.mat()?;
+        if gain.rows() != 1 || bias.rows() != 1 || gain.cols() != bias.cols() {
+            return Err(save::invalid("norm parameters do not line up"));
+        }
+        let mut norm = RowNorm::new(gain.cols(), center);
+        norm.eps = eps;
+        norm.gain = gain;
+        norm.bias = bias;
+        Ok(norm)
+    }
+}
+
+#[derive(Clone)]
+pub struct LayerNorm {
+    norm: RowNorm,
+}
+
+impl LayerNorm {
+    pub fn new(features: usize) -> LayerNorm {
+        LayerNorm {
+            norm: RowNorm::new(features, true),
+        }
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> LayerNorm {
+        assert!(eps > 0.0);
+        self.norm.eps = eps;
+        self
+    }
+
+    pub fn features(&self) -> usize {
+        self.norm.gain.cols()
+    }
+
+    pub fn load(reader: &mut Reader, _registry: &Registry) -> io::Result<Box<dyn Layer>> {
+        Ok(Box::new(LayerNorm {
+            norm: RowNorm::load(reader, true)?,
+        }))
+    }
+}
+
+impl Layer for LayerNorm {
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        self.norm.forward(input)
+    }
+
+    fn infer(&self, input: &MatF64) -> MatF64 {
+        self.norm.normalize(input).2
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        self.norm.backward(cache, grad)
+    }
+
+    fn shape(&self) -> Option<(usize, usize)> {
+        Some((self.features(), self.features()))
+    }
+
+    fn params(&self) -> Vec<&MatF64> {
+        vec![&self.norm.gain, &self.norm.bias]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        vec![&mut self.norm.gain, &mut self.norm.bias]
+    }
+
+    fn regularized(&self) -> bool {
+        false
+    }
+
+    fn kind(&self) -> &str {
+        "layer_norm"
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.norm.write_to(w)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct RmsNorm {
+    norm: RowNorm,
+}
+
+impl RmsNorm {
+    pub fn new(features: usize) -> RmsNorm {
+        RmsNorm {
+            norm: RowNorm::new(features, false),
+        }
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> RmsNorm {
+        assert!(eps > 0.0);
+        self.norm.eps = eps;
+        self
+    }
+
+    pub fn features(&self) -> usize {
+        self.norm.gain.cols()
+    }
+
+    pub fn load(reader: &mut Reader, _registry: &Registry) -> io::Result<Box<dyn Layer>> {
+        Ok(Box::new(RmsNorm {
+            norm: RowNorm::load(reader, false)?,
+        }))
+    }
+}
+
+impl Layer for RmsNorm {
+    fn forward(&self, input: &MatF64) -> (MatF64, Cache) {
+        self.norm.forward(input)
+    }
+
+    fn infer(&self, input: &MatF64) -> MatF64 {
+        self.norm.normalize(input).2
+    }
+
+    fn backward(&self, cache: &Cache, grad: &MatF64) -> (MatF64, Vec<MatF64>) {
+        self.norm.backward(cache, grad)
+    }
+
+    fn shape(&self) -> Option<(usize, usize)> {
+        Some((self.features(), self.features()))
+    }
+
+    fn params(&self) -> Vec<&MatF64> {
+        vec![&self.norm.gain, &self.norm.bias]
+    }
+
+    fn params_mut(&mut self) -> Vec<&mut MatF64> {
+        vec![&mut self.norm.gain, &mut self.norm.bias]
+    }
+
+    fn regularized(&self) -> bool {
+        false
+    }
+
+    fn kind(&self) -> &str {
+        "rms_norm"
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.norm.write_to(w)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn check_gradients(layer: &dyn Layer, input: &MatF64) {
+    // loss = sum(out * weights) with fixed random weights, so d loss / d out = weights
+    let (out, cache) = layer.forward(input);
+    let weights = MatF64::rand(out.rows(), out.cols());
+    let (input_grad, param_grads) = layer.backward(&cache, &weights);
+
+    let loss = |probe: &dyn Layer, x: &MatF64| -> f64 {
+        probe
+            .forward(x)
+            .0
+            .iter()
+            .zip(weights.iter())
+            .map(|(a, b)| a * b)
+            .sum()
+    };
+    let assert_close = |numeric: f64, analytic: f64| {
+        assert!(
+            (numeric - analytic).abs() < 1e-5,
+            "numeric {} analytic {}",
+            numeric,
+            analytic
+        );
+    };
+
+    let eps = 1e-6;
+    for i in 0..input.len() {
+        let (r, c) = (i / input.cols(), i % input.cols());
+        let mut plus = input.clone();
+        plus[(r, c)] += eps;
+        let mut minus = input.clone();
+        minus[(r, c)] -= eps;
+        let numeric = (loss(layer.boxed_clone().as_ref(), &plus)
+            - loss(layer.boxed_clone().as_ref(), &minus))
+            / (2.0 * eps);
+        assert_close(numeric, input_grad[(r, c)]);
+    }
+
+    for (p, grad) in param_grads.iter().enumerate() {
+        for i in 0..grad.len() {
+            let (r, c) = (i / grad.cols(), i % grad.cols());
+            let mut plus = layer.boxed_clone();
+            plus.params_mut()[p][(r, c)] += eps;
+            let mut minus = layer.boxed_clone();
+            minus.params_mut()[p][(r, c)] -= eps;
+            let numeric = (loss(plus.as_ref(), input) - loss(minus.as_ref(), input)) / (2.0 * eps);
+            assert_close(numeric, grad[(r, c)]);
+        }
+    }
+}
+
+#[test]
+fn test_batch_norm_forward() {
+    let mut norm = BatchNorm::new(3);
+    let input = MatF64::rand(8, 3);
+    let (out, _) = norm.forward(&input);
+
+    for j in 0..3 {
+        let mean = (0..8).map(|r| out[(r, j)]).sum::<f64>() / 8.0;
+        let var = (0..8).map(|r| (out[(r, j)] - mean).powi(2)).sum::<f64>() / 8.0;
+        assert!(mean.abs() < 1e-9);
+        assert!((var - 1.0).abs() < 1e-3);
+    }
+    assert!(norm.running_mean().iter().any(|m| *m != 0.0));
+
+    // inference uses the running statistics in either mode
+    let row = MatF64::rand(1, 3);
+    assert_ne!(norm.infer(&row).to_vec(), vec![0.0; 3]);
+    let mut eval = norm.clone();
+    eval.set_mode(Mode::Eval);
+    assert_eq!(norm.infer(&row), eval.infer(&row));
+
+    norm.set_mode(Mode::Eval);
+    assert_eq!(norm.forward(&input).0, norm.infer(&input));
+    check_gradients(&norm, &input);
+
+    let mut saved = Vec::new();
+    norm.write_to(&mut saved).unwrap();
+    let saved = String::from_utf8(saved).unwrap();
+    let (_, mats) = saved.split_once('\n').unwrap();
+    let load = |header: &str| {
+        let text = format!("{}\n{}", header, mats);
+        let mut reader = Reader::new(text.as_bytes()).unwrap();
+        BatchNorm::load(&mut reader, &Registry::default())
+    };
+    assert!(load("0.1 0.001").is_ok());
+    assert!(load("0.1 0").is_err());
+    assert!(load("1.5 0.001").is_err());
+}
+
+#[test]
+fn test_batch_norm_backward() {
+    let mut norm = BatchNorm::new(4);
+    norm.params_mut()[0].iter_mut().for_each(|g| *g = rand::random::<f64>() + 0.5);
+    check_gradients(&norm, &MatF64::rand(5, 4));
+}
+
+#[test]
+fn test_layer_norm_backward() {
+    let mut norm = LayerNorm::new(5);
+    norm.params_mut()[0].iter_mut().for_each(|g| *g = rand::random::<f64>() + 0.5);
+    norm.params_mut()[1].iter_mut().for_each(|b| *b = rand::random::<f64>());
+    check_gradients(&norm, &MatF64::rand(3, 5));
+
+    // batch size one is fine, every row is normalized on its own
+    let (out, _) = norm.forward(&MatF64::rand(1, 5));
+    assert_eq!(out.rows(), 1);
+}
+
+#[test]
+fn test_rms_norm_backward() {
+    let mut norm = RmsNorm::new(4);
+    norm.params_mut()[0].iter_mut().for_each(|g| *g = rand::random::<f64>() + 0.5);
+    check_gradients(&norm, &MatF64::rand(3, 4));
+
+    let input = crate::mat!((3.0, -4.0));
+    let out = RmsNorm::new(2).with_eps(1e-12).infer(&input);
+    let expected = crate::mat!((3.0 / 12.5f64.sqrt(), -4.0 / 12.5f64.sqrt()));
+    assert!((&out - &expected).iter().all(|d| d.abs() < 1e-9));
+}
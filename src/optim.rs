@@ -0,0 +1,280 @@
+use crate::{
+    grad::Gradients,
+    nn::Model,
+    save::{self, Reader},
+};
+use std::io::{self, Write};
+
+/// Turns gradients into a parameter update, weight decay stays with `Model::learn`.
+pub trait Optimizer: Send {
+    fn step(&mut self, model: &mut Model, gradients: &Gradients, rate: f64);
+
+    /// Writes the accumulated state, e.g. moments, for checkpoints.
+    fn write_state(&self, _w: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_state(&mut self, _r: &mut Reader) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Optimizer + ?Sized> Optimizer for Box<T> {
+    fn step(&mut self, model: &mut Model, gradients: &Gradients, rate: f64) {
+        (**self).step(model, gradients, rate)
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).write_state(w)
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        (**self).read_state(r)
+    }
+}
+
+fn write_gradients(w: &mut dyn Write, gradients: Option<&Gradients>) -> io::Result<()> {
+    let Some(gradients) = gradients else {
+        return writeln!(w, "none");
+    };
+    writeln!(w, "gradients {}", gradients.layers())?;
+    for (weights, biases) in gradients.weights.iter().zip(gradients.biases.iter()) {
+        save::write_mat(w, weights)?;
+        save::write_mat(w, biases)?;
+    }
+    Ok(())
+}
+
+fn read_gradients(r: &mut Reader) -> io::Result<Option<Gradients>> {
+    match r.word()?.as_str() {
+        "none" => Ok(None),
+        "gradients" => {
+            let layers = r.usize()?;
+            let mut weights = Vec::with_capacity(layers);
+            let mut biases = Vec::with_capacity(layers);
+            for _ in 0..layers {
+                weights.push(r.mat()?);
+                biases.push(r.mat()?);
+            }
+            Ok(Some(Gradients::new(weights, biases)))
+        }
+        word => Err(save::invalid(format!(
+            "expected gradients, found `{}`",
+            word
+        ))),
+    }
+}
+
+/// Plain gradient descent, the same as calling `Model::learn`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sgd;
+
+impl Optimizer for Sgd {
+    fn step(&mut self, model: &mut Model, gradients: &Gradients, rate: f64) {
+        model.learn(gradients, rate);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Momentum {
+    pub momentum: f64,
+    pub nesterov: bool,
+    velocity: Option<Gradients>,
+}
+
+impl Momentum {
+    pub fn new(momentum: f64) -> Momentum {
+        assert!((0.0..1.0).contains(&momentum));
+        Momentum {
+            momentum,
+            nesterov: false,
+            velocity: None,
+        }
+    }
+
+    pub fn with_nesterov(mut self) -> Momentum {
+        self.nesterov = true;
+        self
+    }
+}
+
+impl Optimizer for Momentum {
+    fn step(&mut self, model: &mut Model, gradients: &Gradients, rate: f64) {
+        let velocity = self.velocity.get_or_insert_with(|| gradients.zeros_like());
+        *velocity *= self.momentum;
+        *velocity += gradients;
+
+        if self.nesterov {
+            let mut update = gradients.clone();
+            update.add_scaled(velocity, self.momentum);
+            model.learn(&update, rate);
+        } else {
+            model.learn(velocity, rate);
+        }
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "momentum ")?;
+        write_gradients(w, self.velocity.as_ref())
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        r.expect("momentum")?;
+        self.velocity = read_gradients(r)?;
+        Ok(())
+    }
+}
+
+/// Adam, with `Model::set_weight_decay` this is AdamW.
+#[derive(Clone, Debug)]
+pub struct Adam {
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+    steps: usize,
+    moments: Option<(Gradients, Gradients)>,
+}
+
+impl Default for Adam {
+    fn default() -> Self {
+        Adam {
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            steps: 0,
+            moments: None,
+        }
+    }
+}
+
+impl Adam {
+    pub fn new() -> Adam {
+        Adam::default()
+    }
+
+    pub fn with_betas(mut self, beta1: f64, beta2: f64) -> Adam {
+        assert!((0.0..1.0).contains(&beta1) && (0.0..1.0).contains(&beta2));
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> Adam {
+        self.eps = eps;
+        self
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, model: &mut Model, gradients: &Gradients, rate: f64) {
+        let (m, v) = self
+            .moments
+            .get_or_insert_with(|| (gradients.zeros_like(), gradients.zeros_like()));
+        self.steps += 1;
+
+        let (b1, b2) = (self.beta1, self.beta2);
+        let c1 = 1.0 - b1.powi(self.steps as i32);
+        let c2 = 1.0 - b2.powi(self.steps as i32);
+
+        let mut update = gradients.clone();
+        m.iter_mut()
+            .zip(v.iter_mut())
+            .zip(update.iter_mut())
+            .for_each(|((m, v), g)| {
+                *m = b1 * *m + (1.0 - b1) * *g;
+                *v = b2 * *v + (1.0 - b2) * *g * *g;
+                *g = (*m / c1) / ((*v / c2).sqrt() + self.eps);
+            });
+        model.learn(&update, rate);
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "adam {} ", self.steps)?;
+        write_gradients(w, self.moments.as_ref().map(|(m, _)| m))?;
+        write_gradients(w, self.moments.as_ref().map(|(_, v)| v))
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        r.expect("adam")?;
+        self.steps = r.usize()?;
+        self.moments = match (read_gradients(r)?, read_gradients(r)?) {
+            (Some(m), Some(v)) => Some((m, v)),
+            (None, None) => None,
+            _ => return Err(save::invalid("adam needs both moments")),
+        };
+        Ok(())
+    }
+}
+
+#[test]
+fn test_optimizers_descend() {
+    use crate::batch::TrainingBatch;
+
+    let mut batch = TrainingBatch::empty(2, 1);
+    batch.add(&[0.0, 1.0], &[0.9]);
+    batch.add(&[1.0, 0.0], &[0.1]);
+
+    let optimizers: Vec<Box<dyn Optimizer>> = vec![
+        Box::new(Sgd),
+        Box::new(Momentum::new(0.9)),
+        Box::new(Momentum::new(0.9).with_nesterov()),
+        Box::new(Adam::new()),
+    ];
+    for mut optimizer in optimizers {
+        let mut model = Model::new(&[2, 1]);
+        let before = model.cost(&batch);
+        for _ in 0..50 {
+            let gradients = model.gradient(&batch);
+            optimizer.step(&mut model, &gradients, 0.1);
+        }
+        assert!(model.cost(&batch) < before);
+    }
+}
+
+#[test]
+fn test_adam_first_step() {
+    use crate::mat::MatF64;
+
+    // bias correction makes the first step rate * sign(g)
+    let mut model = Model::new(&[1, 1]);
+    model.set_flat_params(&[0.0, 0.0]);
+    let gradients = Gradients::new(vec![crate::mat!((0.3))], vec![crate::mat!((-2.0))]);
+    Adam::new().step(&mut model, &gradients, 0.01);
+    let params = model.flat_params();
+    assert!((params[0] + 0.01).abs() < 1e-9);
+    assert!((params[1] - 0.01).abs() < 1e-9);
+}
+
+#[test]
+fn test_optimizer_state_roundtrip() {
+    use crate::{batch::TrainingBatch, mat::MatF64};
+
+    let batch = TrainingBatch::new(MatF64::rand(4, 2), MatF64::rand(4, 1));
+    let optimizers: Vec<(Box<dyn Optimizer>, Box<dyn Optimizer>)> = vec![
+        (Box::new(Momentum::new(0.9)), Box::new(Momentum::new(0.9))),
+        (Box::new(Adam::new()), Box::new(Adam::new())),
+    ];
+    for (mut optimizer, mut restored) in optimizers {
+        let mut model = Model::new(&[2, 3, 1]);
+        for _ in 0..3 {
+            let gradients = model.gradient(&batch);
+            optimizer.step(&mut model, &gradients, 0.1);
+        }
+
+        let mut state = Vec::new();
+        optimizer.write_state(&mut state).unwrap();
+        restored
+            .read_state(&mut Reader::new(state.as_slice()).unwrap())
+            .unwrap();
+
+        let mut other = model.clone();
+        let gradients = model.gradient(&batch);
+        optimizer.step(&mut model, &gradients, 0.1);
+        restored.step(&mut other, &gradients, 0.1);
+        assert_eq!(model.flat_params(), other.flat_params());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    batch::TrainingBatch,
+    mat::MatF64,
+    nn::Model,
+    registry::Registry,
+    save::Reader,
+    scale::{read_optional_scaler, Scaler},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// A model with the scalers of its inputs and targets, saved as one file so
+/// `forward` always takes raw features and returns raw targets.
+pub struct Pipeline {
+    model: Model,
+    inputs: Option<Box<dyn Scaler>>,
+    targets: Option<Box<dyn Scaler>>,
+}
+
+impl Pipeline {
+    pub fn new(model: Model) -> Pipeline {
+        Pipeline {
+            model,
+            inputs: None,
+            targets: None,
+        }
+    }
+
+    pub fn with_input_scaler(mut self, scaler: impl Scaler + 'static) -> Pipeline {
+        self.inputs = Some(Box::new(scaler));
+        self
+    }
+
+    pub fn with_target_scaler(mut self, scaler: impl Scaler + 'static) -> Pipeline {
+        self.targets = Some(Box::new(scaler));
+        self
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    pub fn model_mut(&mut self) -> &mut Model {
+        &mut self.model
+    }
+
+    pub fn input_scaler(&self) -> Option<&dyn Scaler> {
+        self.inputs.as_deref()
+    }
+
+    pub fn target_scaler(&self) -> Option<&dyn Scaler> {
+        self.targets.as_deref()
+    }
+
+    /// Fits the scalers, the model is trained on `transform`ed batches.
+    pub fn fit_scalers(&mut self, batch: &TrainingBatch) {
+        if let Some(scaler) = self.inputs.as_mut() {
+            scaler.fit(&batch.input);
+        }
+        if let Some(scaler) = self.targets.as_mut() {
+            scaler.fit(&batch.expected);
+        }
+    }
+
+    /// The batch as the model sees it.
+    pub fn transform(&self, batch: &TrainingBatch) -> TrainingBatch {
+        let mut batch = batch.clone();
+        if let Some(scaler) = self.inputs.as_ref() {
+            scaler.apply(&mut batch.input);
+        }
+        if let Some(scaler) = self.targets.as_ref() {
+            scaler.apply(&mut batch.expected);
+        }
+        batch
+    }
+
+    /// Raw outputs for raw inputs, one sample per row.
+    pub fn infer(&self, input: &MatF64) -> MatF64 {
+        let mut input = input.clone();
+        if let Some(scaler) = self.inputs.as_ref() {
+            scaler.apply(&mut input);
+        }
+        let mut output = self.model.net().infer(&input);
+        if let Some(scaler) = self.targets.as_ref() {
+            scaler.inverse(&mut output);
+        }
+        output
+    }
+
+    pub fn forward(&self, input: &[f64]) -> Vec<f64> {
+        self.infer(&MatF64::row_from_slice(input)).to_vec()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>, registry: &Registry) -> io::Result<Pipeline> {
+        Pipeline::read_from(File::open(path)?, registry)
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "snail_pipeline 1")?;
+        for scaler in [&self.inputs, &self.targets] {
+            match scaler {
+                Some(scaler) => scaler.write_to(w)?,
+                None => writeln!(w, "none")?,
+            }
+        }
+        self.model.write_to(w)
+    }
+
+    pub fn read_from(r: impl Read, registry: &Registry) -> io::Result<Pipeline> {
+        let mut reader = Reader::new(r)?;
+        reader.expect("snail_pipeline")?;
+        reader.expect("1")?;
+        let inputs = read_optional_scaler(&mut reader)?;
+        let targets = read_optional_scaler(&mut reader)?;
+        let model = Model::read(&mut reader, registry)?;
+        Ok(Pipeline {
+            model,
+            inputs,
+            targets,
+        })
+    }
+}
+
+#[test]
+fn test_pipeline_roundtrip() {
+    use crate::{
+        optim::Adam,
+        rng::Rng,
+        scale::{MinMaxScaler, StandardScaler},
+        train::Trainer,
+    };
+
+    // y = 2 * (x0 - x1) + 500 on inputs far from the origin
+    let mut rng = Rng::new(5);
+    let mut batch = TrainingBatch::empty(2, 1);
+    for _ in 0..64 {
+        let (a, b) = (rng.uniform() * 50.0 + 1000.0, rng.uniform() * 50.0 + 1000.0);
+        batch.add(&[a, b], &[(a - b) * 2.0 + 500.0]);
+    }
+
+    let mut model = Model::new(&[2, 4, 1]);
+    let params = (0..model.param_count())
+        .map(|_| rng.uniform() * 2.0 - 1.0)
+        .collect::<Vec<f64>>();
+    model.set_flat_params(&params);
+    let mut pipeline = Pipeline::new(model)
+        .with_input_scaler(StandardScaler::new())
+        .with_target_scaler(MinMaxScaler::new());
+    pipeline.fit_scalers(&batch);
+    let scaled = pipeline.transform(&batch);
+    assert!(scaled.expected.iter().all(|v| (0.0..=1.0).contains(v)));
+
+    Trainer::new(300)
+        .with_optimizer(Adam::new())
+        .with_rate(0.01)
+        .fit(pipeline.model_mut(), &scaled);
+    let raw = pipeline.infer(&batch.input);
+    let error = raw
+        .iter()
+        .zip(batch.expected.iter())
+        .map(|(o, e)| (o - e).abs())
+        .sum::<f64>()
+        / batch.samples() as f64;
+    assert!(error < 10.0, "{}", error);
+
+    let mut saved = Vec::new();
+    pipeline.write_to(&mut saved).unwrap();
+    let loaded = Pipeline::read_from(&saved[..], &Registry::default()).unwrap();
+    assert_eq!(loaded.infer(&batch.input).to_vec(), raw.to_vec());
+    assert_eq!(
+        loaded.forward(batch.input.get_row(0)),
+        vec![raw.get_row(0)[0]]
+    );
+    assert_eq!(loaded.input_scaler().unwrap().name(), "standard");
+}
@@ -0,0 +1,102 @@
+use crate::mat::MatF64;
+
+/// Elastic net penalty `l1 * sum(|w|) + l2 * sum(w²)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Penalty {
+    pub l1: f64,
+    pub l2: f64,
+}
+
+impl Penalty {
+    pub fn none() -> Penalty {
+        Penalty::default()
+    }
+
+    pub fn l1(strength: f64) -> Penalty {
+        Penalty {
+            l1: strength,
+            l2: 0.0,
+        }
+    }
+
+    pub fn l2(strength: f64) -> Penalty {
+        Penalty {
+            l1: 0.0,
+            l2: strength,
+        }
+    }
+
+    /// `ratio` of 1.0 is pure L1, 0.0 pure L2.
+    pub fn elastic_net(strength: f64, ratio: f64) -> Penalty {
+        assert!((0.0..=1.0).contains(&ratio));
+        Penalty {
+            l1: strength * ratio,
+            l2: strength * (1.0 - ratio),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    pub fn cost(&self, m: &MatF64) -> f64 {
+        if self.is_none() {
+            return 0.0;
+        }
+        m.iter().map(|w| self.l1 * w.abs() + self.l2 * w * w).sum()
+    }
+
+    /// Adds half the penalty derivative, matching `Model::gradient` which
+    /// is the gradient of half the cost.
+    pub fn add_gradient(&self, m: &MatF64, grad: &mut MatF64) {
+        if self.is_none() {
+            return;
+        }
+        grad.iter_mut()
+            .zip(m.iter())
+            .for_each(|(g, w)| *g += 0.5 * self.l1 * sign(*w) + self.l2 * w);
+    }
+}
+
+fn sign(x: f64) -> f64 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Regularization {
+    pub weights: Penalty,
+    pub biases: Penalty,
+}
+
+impl Regularization {
+    pub fn weights(penalty: Penalty) -> Regularization {
+        Regularization {
+            weights: penalty,
+            biases: Penalty::none(),
+        }
+    }
+
+    pub fn all(penalty: Penalty) -> Regularization {
+        Regularization {
+            weights: penalty,
+            biases: penalty,
+        }
+    }
+}
+
+#[test]
+fn test_penalty() {
+    let m = crate::mat!((1.0, -2.0, 0.0));
+    assert_eq!(Penalty::l1(0.5).cost(&m), 1.5);
+    assert_eq!(Penalty::l2(0.5).cost(&m), 2.5);
+
+    let mut grad = MatF64::zeros_row(3);
+    Penalty::elastic_net(1.0, 0.5).add_gradient(&m, &mut grad);
+    assert_eq!(grad, crate::mat!((0.75, -1.25, 0.0)));
+}
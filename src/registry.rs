@@ -0,0 +1,65 @@
+use crate::{
+    act::{Activation, ActivationFn},
+    layer::{Dense, Dropout, Layer},
+    norm::{BatchNorm, LayerNorm, RmsNorm},
+    save::Reader,
+};
+use std::{collections::HashMap, io, sync::Arc};
+
+pub type LayerLoader = fn(&mut Reader, &Registry) -> io::Result<Box<dyn Layer>>;
+
+pub struct Registry {
+    activations: HashMap<String, Arc<dyn ActivationFn>>,
+    layers: HashMap<String, LayerLoader>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        registry.register_activation(Activation::Sigmoid);
+        registry.register_activation(Activation::Tanh);
+        registry.register_activation(Activation::ReLU);
+        registry.register_layer("dense", Dense::load);
+        registry.register_layer("dropout", Dropout::load);
+        registry.register_layer("batch_norm", BatchNorm::load);
+        registry.register_layer("layer_norm", LayerNorm::load);
+        registry.register_layer("rms_norm", RmsNorm::load);
+        registry
+    }
+}
+
+impl Registry {
+    pub fn empty() -> Registry {
+        Registry {
+            activations: HashMap::new(),
+            layers: HashMap::new(),
+        }
+    }
+
+    fn check_name(name: &str) {
+        assert!(
+            !name.is_empty() && !name.contains(char::is_whitespace),
+            "registered name must be a single word: {:?}",
+            name
+        );
+    }
+
+    pub fn register_activation(&mut self, activation: impl ActivationFn + 'static) {
+        let name = activation.name().to_string();
+        Registry::check_name(&name);
+        self.activations.insert(name, Arc::new(activation));
+    }
+
+    pub fn activation(&self, name: &str) -> Option<Arc<dyn ActivationFn>> {
+        self.activations.get(name).cloned()
+    }
+
+    pub fn register_layer(&mut self, kind: &str, loader: LayerLoader) {
+        Registry::check_name(kind);
+        self.layers.insert(kind.to_string(), loader);
+    }
+
+    pub fn layer(&self, kind: &str) -> Option<LayerLoader> {
+        self.layers.get(kind).copied()
+    }
+}
@@ -0,0 +1,117 @@
+use rand::{Error, RngCore, SeedableRng};
+
+/// Small splitmix64 generator, used wherever results have to be reproducible from a seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn from_entropy() -> Rng {
+        Rng::new(rand::random())
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, by Box-Muller.
+    pub fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    /// Uniform index in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0);
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Fisher-Yates, kept here instead of `rand::seq` so orders stay stable
+    /// across `rand` versions.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    /// Independent generator for the `index`-th stream of `seed`, e.g. one per epoch.
+    pub fn derive(seed: u64, index: u64) -> Rng {
+        let mut rng = Rng::new(seed ^ index.wrapping_mul(0xd1b5_4a32_d192_ed03));
+        Rng::new(rng.next_u64())
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core_fill(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        rand_core_fill(self, dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Rng {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Rng::new(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Rng::new(seed)
+    }
+}
+
+fn rand_core_fill(rng: &mut Rng, dest: &mut [u8]) {
+    for chunk in dest.chunks_mut(8) {
+        let bytes = rng.next_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+#[test]
+fn test_rng_seeded() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    let xs = (0..10).map(|_| a.uniform()).collect::<Vec<f64>>();
+    let ys = (0..10).map(|_| b.uniform()).collect::<Vec<f64>>();
+    assert_eq!(xs, ys);
+    assert!(xs.iter().all(|x| (0.0..1.0).contains(x)));
+}
+
+#[test]
+fn test_shuffle() {
+    let mut items = (0..50).collect::<Vec<usize>>();
+    Rng::new(1).shuffle(&mut items);
+    assert_ne!(items, (0..50).collect::<Vec<usize>>());
+    items.sort();
+    assert_eq!(items, (0..50).collect::<Vec<usize>>());
+
+    assert_ne!(Rng::derive(3, 0).next_u64(), Rng::derive(3, 1).next_u64());
+    assert!((0..100).all(|_| Rng::new(5).below(7) < 7));
+}
@@ -0,0 +1,73 @@
+use crate::mat::MatF64;
+use std::io::{self, Read, Write};
+
+pub fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+pub fn write_mat(w: &mut dyn Write, m: &MatF64) -> io::Result<()> {
+    write!(w, "{} {}", m.rows(), m.cols())?;
+    for v in m.iter() {
+        write!(w, " {}", v)?;
+    }
+    writeln!(w)
+}
+
+pub struct Reader {
+    tokens: std::vec::IntoIter<String>,
+}
+
+impl Reader {
+    pub fn new(mut r: impl Read) -> io::Result<Reader> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let tokens = text
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        Ok(Reader {
+            tokens: tokens.into_iter(),
+        })
+    }
+
+    pub fn word(&mut self) -> io::Result<String> {
+        self.tokens
+            .next()
+            .ok_or_else(|| invalid("unexpected end of input"))
+    }
+
+    pub fn expect(&mut self, tag: &str) -> io::Result<()> {
+        let word = self.word()?;
+        if word != tag {
+            return Err(invalid(format!("expected `{}`, found `{}`", tag, word)));
+        }
+        Ok(())
+    }
+
+    pub fn usize(&mut self) -> io::Result<usize> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| invalid(format!("expected integer, found `{}`", word)))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| invalid(format!("expected integer, found `{}`", word)))
+    }
+
+    pub fn f64(&mut self) -> io::Result<f64> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| invalid(format!("expected number, found `{}`", word)))
+    }
+
+    pub fn mat(&mut self) -> io::Result<MatF64> {
+        let rows = self.usize()?;
+        let cols = self.usize()?;
+        let data = (0..rows * cols)
+            .map(|_| self.f64())
+            .collect::<io::Result<Vec<f64>>>()?;
+        Ok(MatF64::new(&data, rows, cols))
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    mat::MatF64,
+    save::{self, Reader},
+};
+use std::io::{self, Write};
+
+/// A per column affine transform `(x - center) / scale` fitted on data.
+///
+/// Columns without spread get a scale of one so they are only shifted.
+pub trait Scaler: Send + Sync {
+    fn fit(&mut self, data: &MatF64);
+    fn center(&self) -> &[f64];
+    fn scale(&self) -> &[f64];
+    fn name(&self) -> &str;
+
+    fn apply(&self, data: &mut MatF64) {
+        self.check(data);
+        for row in data.iter_rows_mut() {
+            for ((v, c), s) in row.iter_mut().zip(self.center()).zip(self.scale()) {
+                *v = (*v - c) / s;
+            }
+        }
+    }
+
+    fn inverse(&self, data: &mut MatF64) {
+        self.check(data);
+        for row in data.iter_rows_mut() {
+            for ((v, c), s) in row.iter_mut().zip(self.center()).zip(self.scale()) {
+                *v = *v * s + c;
+            }
+        }
+    }
+
+    fn check(&self, data: &MatF64) {
+        assert_eq!(
+            self.center().len(),
+            data.cols(),
+            "{} scaler is not fitted to {} columns",
+            self.name(),
+            data.cols()
+        );
+    }
+
+    fn write_to(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "scaler {}", self.name())?;
+        save::write_mat(w, &MatF64::row_from_slice(self.center()))?;
+        save::write_mat(w, &MatF64::row_from_slice(self.scale()))
+    }
+}
+
+impl<S: Scaler + ?Sized> Scaler for Box<S> {
+    fn fit(&mut self, data: &MatF64) {
+        (**self).fit(data)
+    }
+
+    fn center(&self) -> &[f64] {
+        (**self).center()
+    }
+
+    fn scale(&self) -> &[f64] {
+        (**self).scale()
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+/// Reads one of the built in scalers written by `Scaler::write_to`.
+pub fn read_scaler(reader: &mut Reader) -> io::Result<Box<dyn Scaler>> {
+    reader.expect("scaler")?;
+    read_named(reader)
+}
+
+/// A scaler or `none` in place of a missing one.
+pub(crate) fn read_optional_scaler(reader: &mut Reader) -> io::Result<Option<Box<dyn Scaler>>> {
+    match reader.word()?.as_str() {
+        "none" => Ok(None),
+        "scaler" => read_named(reader).map(Some),
+        word => Err(save::invalid(format!(
+            "expected `scaler` or `none`, found `{}`",
+            word
+        ))),
+    }
+}
+
+fn read_named(reader: &mut Reader) -> io::Result<Box<dyn Scaler>> {
+    let name = reader.word()?;
+    let center = reader.mat()?.to_vec();
+    let scale = reader.mat()?.to_vec();
+    if center.len() != scale.len() {
+        return Err(save::invalid("scaler center and scale differ in length"));
+    }
+    Ok(match name.as_str() {
+        "standard" => Box::new(StandardScaler { center, scale }),
+        "min_max" => Box::new(MinMaxScaler { center, scale }),
+        "robust" => Box::new(RobustScaler { center, scale }),
+        _ => return Err(save::invalid(format!("unknown scaler `{}`", name))),
+    })
+}
+
+fn columns(data: &MatF64) -> Vec<Vec<f64>> {
+    let mut columns = vec![Vec::with_capacity(data.rows()); data.cols()];
+    for row in data.iter_rows() {
+        columns.iter_mut().zip(row).for_each(|(c, v)| c.push(*v));
+    }
+    columns
+}
+
+fn spread(scale: f64) -> f64 {
+    if scale > 0.0 && scale.is_finite() {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Zero mean and unit variance.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StandardScaler {
+    center: Vec<f64>,
+    scale: Vec<f64>,
+}
+
+impl StandardScaler {
+    pub fn new() -> StandardScaler {
+        StandardScaler::default()
+    }
+}
+
+impl Scaler for StandardScaler {
+    fn fit(&mut self, data: &MatF64) {
+        let n = data.rows().max(1) as f64;
+        (self.center, self.scale) = columns(data)
+            .iter()
+            .map(|c| {
+                let mean = c.iter().sum::<f64>() / n;
+                let var = c.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n;
+                (mean, spread(var.sqrt()))
+            })
+            .unzip();
+    }
+
+    fn center(&self) -> &[f64] {
+        &self.center
+    }
+
+    fn scale(&self) -> &[f64] {
+        &self.scale
+    }
+
+    fn name(&self) -> &str {
+        "standard"
+    }
+}
+
+/// Maps the fitted minimum to 0 and maximum to 1.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MinMaxScaler {
+    center: Vec<f64>,
+    scale: Vec<f64>,
+}
+
+impl MinMaxScaler {
+    pub fn new() -> MinMaxScaler {
+        MinMaxScaler::default()
+    }
+}
+
+impl Scaler for MinMaxScaler {
+    fn fit(&mut self, data: &MatF64) {
+        (self.center, self.scale) = columns(data)
+            .iter()
+            .map(|c| {
+                let min = c.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = c.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                (if min.is_finite() { min } else { 0.0 }, spread(max - min))
+            })
+            .unzip();
+    }
+
+    fn center(&self) -> &[f64] {
+        &self.center
+    }
+
+    fn scale(&self) -> &[f64] {
+        &self.scale
+    }
+
+    fn name(&self) -> &str {
+        "min_max"
+    }
+}
+
+/// Centers on the median and divides by the interquartile range, so
+/// outliers barely move the transform.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RobustScaler {
+    center: Vec<f64>,
+    scale: Vec<f64>,
+}
+
+impl RobustScaler {
+    pub fn new() -> RobustScaler {
+        RobustScaler::default()
+    }
+}
+
+// linear interpolation between the closest ranks of a sorted column
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let at = q * (sorted.len() - 1) as f64;
+    let (low, high) = (at.floor() as usize, at.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (at - low as f64)
+}
+
+impl Scaler for RobustScaler {
+    fn fit(&mut self, data: &MatF64) {
+        (self.center, self.scale) = columns(data)
+            .into_iter()
+            .map(|mut c| {
+                c.sort_by(f64::total_cmp);
+                let iqr = quantile(&c, 0.75) - quantile(&c, 0.25);
+                (quantile(&c, 0.5), spread(iqr))
+            })
+            .unzip();
+    }
+
+    fn center(&self) -> &[f64] {
+        &self.center
+    }
+
+    fn scale(&self) -> &[f64] {
+        &self.scale
+    }
+
+    fn name(&self) -> &str {
+        "robust"
+    }
+}
+
+#[test]
+fn test_scalers() {
+    let data = MatF64::new(&[1.0, 5.0, 2.0, 5.0, 3.0, 5.0, 10.0, 5.0], 4, 2);
+
+    let mut standard = StandardScaler::new();
+    standard.fit(&data);
+    let mut scaled = data.clone();
+    standard.apply(&mut scaled);
+    let first = scaled.iter().step_by(2).copied().collect::<Vec<f64>>();
+    assert!(first.iter().sum::<f64>().abs() < 1e-12);
+    assert!((first.iter().map(|v| v * v).sum::<f64>() / 4.0 - 1.0).abs() < 1e-12);
+    // the constant column is only shifted
+    assert!(scaled.iter().skip(1).step_by(2).all(|v| *v == 0.0));
+
+    let mut min_max = MinMaxScaler::new();
+    min_max.fit(&data);
+    let mut scaled = data.clone();
+    min_max.apply(&mut scaled);
+    assert_eq!(
+        scaled.to_vec(),
+        vec![0.0, 0.0, 1.0 / 9.0, 0.0, 2.0 / 9.0, 0.0, 1.0, 0.0]
+    );
+
+    let mut robust = RobustScaler::new();
+    robust.fit(&data);
+    assert_eq!(robust.center(), &[2.5, 5.0]);
+    assert_eq!(robust.scale(), &[3.0, 1.0]);
+
+    for scaler in [&standard as &dyn Scaler, &min_max, &robust] {
+        let mut roundtrip = data.clone();
+        scaler.apply(&mut roundtrip);
+        scaler.inverse(&mut roundtrip);
+        assert!(roundtrip
+            .iter()
+            .zip(data.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let mut saved = Vec::new();
+        scaler.write_to(&mut saved).unwrap();
+        let loaded = read_scaler(&mut Reader::new(&saved[..]).unwrap()).unwrap();
+        assert_eq!(loaded.name(), scaler.name());
+        assert_eq!(loaded.center(), scaler.center());
+        assert_eq!(loaded.scale(), scaler.scale());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::save::Reader;
+use std::{
+    f64::consts::PI,
+    io::{self, Write},
+};
+
+pub trait LrSchedule: Send {
+    /// Learning rate for the zero based optimizer `step`.
+    fn rate(&self, step: usize) -> f64;
+
+    /// Feeds the latest cost, only schedules reacting to progress care.
+    fn observe(&mut self, _cost: f64) {}
+
+    /// Writes what `observe` changed, for checkpoints.
+    fn write_state(&self, _w: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_state(&mut self, _r: &mut Reader) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: LrSchedule + ?Sized> LrSchedule for Box<T> {
+    fn rate(&self, step: usize) -> f64 {
+        (**self).rate(step)
+    }
+
+    fn observe(&mut self, cost: f64) {
+        (**self).observe(cost)
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).write_state(w)
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        (**self).read_state(r)
+    }
+}
+
+/// Keeps the step counter next to a schedule, `set_step` resumes a run.
+pub struct Scheduler {
+    schedule: Box<dyn LrSchedule>,
+    step: usize,
+}
+
+impl Scheduler {
+    pub fn new(schedule: impl LrSchedule + 'static) -> Scheduler {
+        Scheduler {
+            schedule: Box::new(schedule),
+            step: 0,
+        }
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: usize) {
+        self.step = step;
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.schedule.rate(self.step)
+    }
+
+    /// Returns the rate for the current step and advances.
+    pub fn next_rate(&mut self) -> f64 {
+        let rate = self.rate();
+        self.step += 1;
+        rate
+    }
+
+    pub fn observe(&mut self, cost: f64) {
+        self.schedule.observe(cost);
+    }
+
+    pub fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "schedule {}", self.step)?;
+        self.schedule.write_state(w)
+    }
+
+    pub fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        r.expect("schedule")?;
+        self.step = r.usize()?;
+        self.schedule.read_state(r)
+    }
+}
+
+pub struct Constant(pub f64);
+
+impl LrSchedule for Constant {
+    fn rate(&self, _step: usize) -> f64 {
+        self.0
+    }
+}
+
+pub struct StepDecay {
+    pub initial: f64,
+    pub factor: f64,
+    pub every: usize,
+}
+
+impl StepDecay {
+    pub fn new(initial: f64, factor: f64, every: usize) -> StepDecay {
+        assert!(every > 0);
+        StepDecay {
+            initial,
+            factor,
+            every,
+        }
+    }
+}
+
+impl LrSchedule for StepDecay {
+    fn rate(&self, step: usize) -> f64 {
+        self.initial * self.factor.powi((step / self.every) as i32)
+    }
+}
+
+pub struct ExponentialDecay {
+    pub initial: f64,
+    pub gamma: f64,
+}
+
+impl ExponentialDecay {
+    pub fn new(initial: f64, gamma: f64) -> ExponentialDecay {
+        ExponentialDecay { initial, gamma }
+    }
+}
+
+impl LrSchedule for ExponentialDecay {
+    fn rate(&self, step: usize) -> f64 {
+        self.initial * self.gamma.powf(step as f64)
+    }
+}
+
+/// Cosine annealing with warm restarts, the n-th cycle is `period * mult^n` steps long.
+pub struct CosineAnnealing {
+    pub max: f64,
+    pub min: f64,
+    pub period: usize,
+    pub mult: usize,
+}
+
+impl CosineAnnealing {
+    pub fn new(max: f64, min: f64, period: usize) -> CosineAnnealing {
+        assert!(period > 0);
+        CosineAnnealing {
+            max,
+            min,
+            period,
+            mult: 1,
+        }
+    }
+
+    pub fn with_mult(mut self, mult: usize) -> CosineAnnealing {
+        assert!(mult > 0);
+        self.mult = mult;
+        self
+    }
+}
+
+impl LrSchedule for CosineAnnealing {
+    fn rate(&self, step: usize) -> f64 {
+        let (mut position, mut period) = (step, self.period);
+        if self.mult == 1 {
+            position %= period;
+        }
+        // cycles grow geometrically, so this takes O(log step) rounds
+        while position >= period {
+            position -= period;
+            period = period.saturating_mul(self.mult);
+        }
+        let t = position as f64 / period as f64;
+        self.min + 0.5 * (self.max - self.min) * (1.0 + (PI * t).cos())
+    }
+}
+
+/// Ramps linearly up to the wrapped schedule, which starts counting after the warmup.
+pub struct LinearWarmup {
+    pub steps: usize,
+    pub inner: Box<dyn LrSchedule>,
+}
+
+impl LinearWarmup {
+    pub fn new(steps: usize, inner: impl LrSchedule + 'static) -> LinearWarmup {
+        LinearWarmup {
+            steps,
+            inner: Box::new(inner),
+        }
+    }
+}
+
+impl LrSchedule for LinearWarmup {
+    fn rate(&self, step: usize) -> f64 {
+        if step < self.steps {
+            self.inner.rate(0) * (step + 1) as f64 / self.steps as f64
+        } else {
+            self.inner.rate(step - self.steps)
+        }
+    }
+
+    fn observe(&mut self, cost: f64) {
+        self.inner.observe(cost);
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        self.inner.write_state(w)
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        self.inner.read_state(r)
+    }
+}
+
+/// One-cycle policy, cosine up from `max / div` to `max` during the first
+/// `pct_start` of training, then cosine down to `max / final_div`.
+pub struct OneCycle {
+    pub max: f64,
+    pub total: usize,
+    pub pct_start: f64,
+    pub div: f64,
+    pub final_div: f64,
+}
+
+impl OneCycle {
+    pub fn new(max: f64, total: usize) -> OneCycle {
+        assert!(total > 1);
+        OneCycle {
+            max,
+            total,
+            pct_start: 0.3,
+            div: 25.0,
+            final_div: 1e4,
+        }
+    }
+}
+
+impl LrSchedule for OneCycle {
+    fn rate(&self, step: usize) -> f64 {
+        let anneal = |from: f64, to: f64, t: f64| to + 0.5 * (from - to) * (1.0 + (PI * t).cos());
+        let last = (self.total - 1) as f64;
+        // a peak at the last step leaves no annealing phase to divide by
+        let peak = (self.pct_start * last).round().clamp(1.0, last);
+        let step = (step as f64).min(last);
+        if step <= peak {
+            anneal(self.max / self.div, self.max, step / peak)
+        } else {
+            anneal(self.max, self.max / self.final_div, (step - peak) / (last - peak))
+        }
+    }
+}
+
+/// Multiplies the rate by `factor` once the observed cost did not improve
+/// by more than `min_delta` for `patience` observations.
+pub struct ReduceOnPlateau {
+    pub factor: f64,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub min_rate: f64,
+    rate: f64,
+    best: f64,
+    wait: usize,
+}
+
+impl ReduceOnPlateau {
+    pub fn new(rate: f64, factor: f64, patience: usize) -> ReduceOnPlateau {
+        assert!(factor > 0.0 && factor < 1.0);
+        ReduceOnPlateau {
+            factor,
+            patience,
+            min_delta: 0.0,
+            min_rate: 0.0,
+            rate,
+            best: f64::INFINITY,
+            wait: 0,
+        }
+    }
+
+    pub fn with_min_delta(mut self, min_delta: f64) -> ReduceOnPlateau {
+        self.min_delta = min_delta;
+        self
+    }
+
+    pub fn with_min_rate(mut self, min_rate: f64) -> ReduceOnPlateau {
+        self.min_rate = min_rate;
+        self
+    }
+}
+
+impl LrSchedule for ReduceOnPlateau {
+    fn rate(&self, _step: usize) -> f64 {
+        self.rate
+    }
+
+    fn observe(&mut self, cost: f64) {
+        if cost < self.best - self.min_delta {
+            self.best = cost;
+            self.wait = 0;
+            return;
+        }
+        self.wait += 1;
+        if self.wait > self.patience {
+            self.rate = (self.rate * self.factor).max(self.min_rate);
+            self.wait = 0;
+        }
+    }
+
+    fn write_state(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "plateau {} {} {}", self.rate, self.best, self.wait)
+    }
+
+    fn read_state(&mut self, r: &mut Reader) -> io::Result<()> {
+        r.expect("plateau")?;
+        self.rate = r.f64()?;
+        self.best = r.f64()?;
+        self.wait = r.usize()?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_decay() {
+    let step = StepDecay::new(1.0, 0.5, 10);
+    assert_eq!(step.rate(9), 1.0);
+    assert_eq!(step.rate(10), 0.5);
+    assert_eq!(step.rate(25), 0.25);
+
+    let exp = ExponentialDecay::new(2.0, 0.5);
+    assert_eq!(exp.rate(3), 0.25);
+}
+
+#[test]
+fn test_cosine_restarts() {
+    let cosine = CosineAnnealing::new(1.0, 0.0, 4).with_mult(2);
+    assert_eq!(cosine.rate(0), 1.0);
+    assert!((cosine.rate(2) - 0.5).abs() < 1e-12);
+    // second cycle restarts at step 4 and lasts 8 steps
+    assert_eq!(cosine.rate(4), 1.0);
+    assert!((cosine.rate(8) - 0.5).abs() < 1e-12);
+    assert_eq!(cosine.rate(12), 1.0);
+
+    // fixed length cycles and far away steps are cheap
+    let fixed = CosineAnnealing::new(1.0, 0.0, 10);
+    assert!((fixed.rate(1_000_000_005) - 0.5).abs() < 1e-12);
+    assert!(cosine.rate(usize::MAX) <= 1.0);
+}
+
+#[test]
+fn test_warmup_and_one_cycle() {
+    let warmup = LinearWarmup::new(4, Constant(1.0));
+    assert_eq!(warmup.rate(0), 0.25);
+    assert_eq!(warmup.rate(3), 1.0);
+    assert_eq!(warmup.rate(100), 1.0);
+
+    let cycle = OneCycle::new(1.0, 11);
+    assert!((cycle.rate(0) - 0.04).abs() < 1e-12);
+    assert!((cycle.rate(3) - 1.0).abs() < 1e-12);
+    assert!((cycle.rate(10) - 1e-4).abs() < 1e-12);
+    assert!(cycle.rate(5) < cycle.rate(4));
+
+    let mut warmup_only = OneCycle::new(1.0, 2);
+    warmup_only.pct_start = 1.0;
+    assert!((0..4).all(|step| warmup_only.rate(step).is_finite()));
+    assert_eq!(warmup_only.rate(1), 1.0);
+}
+
+#[test]
+fn test_plateau_and_resume() {
+    let mut scheduler = Scheduler::new(ReduceOnPlateau::new(1.0, 0.5, 1));
+    for cost in [3.0, 2.0, 2.0, 2.0] {
+        scheduler.observe(cost);
+    }
+    assert_eq!(scheduler.next_rate(), 0.5);
+
+    let mut resumed = Scheduler::new(StepDecay::new(1.0, 0.1, 5));
+    resumed.set_step(5);
+    assert!((resumed.next_rate() - 0.1).abs() < 1e-12);
+    assert_eq!(resumed.step(), 6);
+
+    let mut state = Vec::new();
+    scheduler.write_state(&mut state).unwrap();
+    let mut restored = Scheduler::new(ReduceOnPlateau::new(1.0, 0.5, 1));
+    restored
+        .read_state(&mut Reader::new(state.as_slice()).unwrap())
+        .unwrap();
+    assert_eq!(restored.step(), 1);
+    for cost in [2.0, 2.0] {
+        scheduler.observe(cost);
+        restored.observe(cost);
+    }
+    assert_eq!(restored.rate(), 0.25);
+    assert_eq!(restored.rate(), scheduler.rate());
+}
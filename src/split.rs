@@ -0,0 +1,266 @@
+use crate::{batch::TrainingBatch, dataset::Dataset, rng::Rng};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Strategy {
+    Random,
+    Stratified,
+    Grouped(Vec<usize>),
+}
+
+/// Partitions the samples of a batch, the same seed always gives the same parts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Splitter {
+    seed: u64,
+    strategy: Strategy,
+}
+
+impl Splitter {
+    pub fn new(seed: u64) -> Splitter {
+        Splitter {
+            seed,
+            strategy: Strategy::Random,
+        }
+    }
+
+    /// Keeps the class proportions of `Dataset::labels` in every part.
+    pub fn stratified(mut self) -> Splitter {
+        self.strategy = Strategy::Stratified;
+        self
+    }
+
+    /// Keeps samples sharing a group id in the same part, one id per sample.
+    /// Part sizes follow the fractions as closely as whole groups allow.
+    pub fn grouped(mut self, groups: &[usize]) -> Splitter {
+        self.strategy = Strategy::Grouped(groups.to_vec());
+        self
+    }
+
+    /// Sample indices of every part, ascending. `fractions` are the relative
+    /// part sizes and must sum to one.
+    pub fn indices<D: Dataset + ?Sized>(&self, data: &D, fractions: &[f64]) -> Vec<Vec<usize>> {
+        assert!(!fractions.is_empty());
+        assert!(fractions.iter().all(|f| *f >= 0.0), "negative fraction");
+        assert!(
+            (fractions.iter().sum::<f64>() - 1.0).abs() < 1e-9,
+            "fractions must sum to one"
+        );
+        let samples = data.len();
+        let mut rng = Rng::new(self.seed);
+        let mut parts = vec![Vec::new(); fractions.len()];
+
+        match &self.strategy {
+            Strategy::Random => {
+                let mut order = (0..samples).collect::<Vec<usize>>();
+                rng.shuffle(&mut order);
+                cut(&order, fractions, &mut parts);
+            }
+            Strategy::Stratified => {
+                let mut classes: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+                for (i, label) in data.labels().into_iter().enumerate() {
+                    classes.entry(label).or_default().push(i);
+                }
+                let mut behind = vec![0.0; fractions.len()];
+                for mut members in classes.into_values() {
+                    rng.shuffle(&mut members);
+                    cut_class(&members, fractions, &mut behind, &mut parts);
+                }
+            }
+            Strategy::Grouped(groups) => {
+                assert_eq!(groups.len(), samples, "one group id per sample");
+                let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+                for (i, group) in groups.iter().enumerate() {
+                    members.entry(*group).or_default().push(i);
+                }
+                let mut members = members.into_values().collect::<Vec<_>>();
+                rng.shuffle(&mut members);
+
+                let bounds = bounds(samples, fractions);
+                let mut part = 0;
+                let mut assigned = 0;
+                for group in members {
+                    while part + 1 < parts.len() && assigned >= bounds[part] {
+                        part += 1;
+                    }
+                    assigned += group.len();
+                    parts[part].extend(group);
+                }
+            }
+        }
+
+        parts.iter_mut().for_each(|p| p.sort_unstable());
+        parts
+    }
+
+    /// `ratio` of the samples in the first part, the rest in the second.
+    pub fn split<D: Dataset + ?Sized>(
+        &self,
+        data: &D,
+        ratio: f64,
+    ) -> (TrainingBatch, TrainingBatch) {
+        assert!((0.0..=1.0).contains(&ratio));
+        let parts = self.indices(data, &[ratio, 1.0 - ratio]);
+        (data.batch(&parts[0]), data.batch(&parts[1]))
+    }
+
+    /// Train, validation and test parts, the test part gets what is left.
+    pub fn split3<D: Dataset + ?Sized>(
+        &self,
+        data: &D,
+        train: f64,
+        validation: f64,
+    ) -> (TrainingBatch, TrainingBatch, TrainingBatch) {
+        let test = 1.0 - train - validation;
+        assert!(test > -1e-9, "train and validation exceed the batch");
+        let parts = self.indices(data, &[train, validation, test.max(0.0)]);
+        (
+            data.batch(&parts[0]),
+            data.batch(&parts[1]),
+            data.batch(&parts[2]),
+        )
+    }
+}
+
+// cumulative part ends, rounded to whole samples
+fn bounds(len: usize, fractions: &[f64]) -> Vec<usize> {
+    let mut total = 0.0;
+    let mut bounds = fractions
+        .iter()
+        .map(|f| {
+            total += f;
+            ((total * len as f64).round() as usize).min(len)
+        })
+        .collect::<Vec<usize>>();
+    *bounds.last_mut().unwrap() = len;
+    bounds
+}
+
+fn cut(order: &[usize], fractions: &[f64], parts: &mut [Vec<usize>]) {
+    let mut start = 0;
+    for (part, end) in parts.iter_mut().zip(bounds(order.len(), fractions)) {
+        part.extend_from_slice(&order[start..end]);
+        start = end;
+    }
+}
+
+// splits one class by the fractions, its leftover samples go to the parts
+// furthest behind their share over all classes so far, so many small classes
+// still fill every part
+fn cut_class(members: &[usize], fractions: &[f64], behind: &mut [f64], parts: &mut [Vec<usize>]) {
+    let n = members.len() as f64;
+    let mut counts = fractions
+        .iter()
+        .map(|f| (n * f + 1e-9).floor() as usize)
+        .collect::<Vec<usize>>();
+    for (k, f) in fractions.iter().enumerate() {
+        behind[k] += n * f - counts[k] as f64;
+    }
+    for _ in counts.iter().sum::<usize>()..members.len() {
+        // ties go to the earlier part
+        let k = (0..fractions.len())
+            .filter(|k| fractions[*k] > 0.0)
+            .max_by(|a, b| behind[*a].total_cmp(&behind[*b]).then(b.cmp(a)))
+            .unwrap();
+        counts[k] += 1;
+        behind[k] -= 1.0;
+    }
+
+    let mut start = 0;
+    for (part, count) in parts.iter_mut().zip(counts) {
+        part.extend_from_slice(&members[start..start + count]);
+        start += count;
+    }
+}
+
+impl TrainingBatch {
+    /// Random split with `ratio` of the samples in the first part.
+    pub fn split(&self, ratio: f64, seed: u64) -> (TrainingBatch, TrainingBatch) {
+        Splitter::new(seed).split(self, ratio)
+    }
+
+    /// Random train, validation and test split.
+    pub fn split3(
+        &self,
+        train: f64,
+        validation: f64,
+        seed: u64,
+    ) -> (TrainingBatch, TrainingBatch, TrainingBatch) {
+        Splitter::new(seed).split3(self, train, validation)
+    }
+}
+
+#[cfg(test)]
+fn labeled(labels: &[usize]) -> TrainingBatch {
+    let mut batch = TrainingBatch::empty(1, 1);
+    for (i, label) in labels.iter().enumerate() {
+        batch.add(&[i as f64], &[*label as f64]);
+    }
+    batch
+}
+
+#[test]
+fn test_split() {
+    let batch = labeled(&[0; 10]);
+    let (train, test) = batch.split(0.8, 1);
+    assert_eq!((train.samples(), test.samples()), (8, 2));
+
+    let mut all = train.input.to_vec();
+    all.extend(test.input.to_vec());
+    all.sort_by(f64::total_cmp);
+    assert_eq!(all, (0..10).map(|i| i as f64).collect::<Vec<f64>>());
+
+    let (again, _) = batch.split(0.8, 1);
+    assert_eq!(again.input.to_vec(), train.input.to_vec());
+    assert!((2..6).any(|seed| batch.split(0.8, seed).0.input.to_vec() != train.input.to_vec()));
+
+    let (a, b, c) = batch.split3(0.6, 0.2, 1);
+    assert_eq!((a.samples(), b.samples(), c.samples()), (6, 2, 2));
+}
+
+#[test]
+fn test_stratified_split() {
+    // 16 of class 0, 4 of class 1
+    let mut labels = vec![0; 16];
+    labels.extend([1; 4]);
+    let batch = labeled(&labels);
+
+    for seed in 0..5 {
+        let (train, test) = Splitter::new(seed).stratified().split(&batch, 0.75);
+        assert_eq!(train.labels().iter().filter(|l| **l == 1).count(), 3);
+        assert_eq!(test.labels().iter().filter(|l| **l == 1).count(), 1);
+        assert_eq!(test.samples(), 5);
+    }
+
+    // more classes than samples per class, leftovers rotate over the parts
+    let labels = (0..30).map(|i| i / 3).collect::<Vec<usize>>();
+    let parts = Splitter::new(2)
+        .stratified()
+        .indices(&labeled(&labels), &[0.2; 5]);
+    assert_eq!(parts.iter().map(Vec::len).collect::<Vec<_>>(), vec![6; 5]);
+    let (train, test) = Splitter::new(2).stratified().split(&labeled(&labels), 0.5);
+    assert_eq!((train.samples(), test.samples()), (15, 15));
+}
+
+#[test]
+fn test_group_split() {
+    let batch = labeled(&[0; 12]);
+    let groups = [0, 0, 0, 1, 1, 1, 2, 2, 2, 3, 3, 3];
+    let splitter = Splitter::new(3).grouped(&groups);
+    let parts = splitter.indices(&batch, &[0.5, 0.25, 0.25]);
+
+    assert_eq!(
+        parts.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![6, 3, 3]
+    );
+    for part in parts.iter() {
+        for i in part {
+            let group = groups[*i];
+            let members = groups.iter().filter(|g| **g == group).count();
+            assert_eq!(
+                part.iter().filter(|j| groups[**j] == group).count(),
+                members
+            );
+        }
+    }
+    assert_eq!(parts, splitter.indices(&batch, &[0.5, 0.25, 0.25]));
+}
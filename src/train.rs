@@ -0,0 +1,768 @@
+use crate::{
+    augment::Augmenter,
+    batch::TrainingBatch,
+    checkpoint::Checkpoints,
+    dataset::Dataset,
+    layer::Mode,
+    loss::LossFn,
+    nn::Model,
+    optim::{Optimizer, Sgd},
+    registry::Registry,
+    rng::Rng,
+    save::{self, Reader},
+    schedule::{Constant, LrSchedule, Scheduler},
+    weight::WeightedSampler,
+};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Named values of a training step or epoch, e.g. "cost", "rate" and "grad_norm".
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub epoch: usize,
+    pub step: usize,
+    values: BTreeMap<String, f64>,
+}
+
+impl Metrics {
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+}
+
+/// Metrics at the end of every finished epoch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub epochs: Vec<Metrics>,
+    pub stopped: bool,
+}
+
+impl History {
+    pub fn last(&self) -> Option<&Metrics> {
+        self.epochs.last()
+    }
+
+    /// One value per epoch that recorded `name`.
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.epochs.iter().filter_map(|m| m.get(name)).collect()
+    }
+}
+
+pub trait Callback: Send {
+    fn on_batch_end(&mut self, _model: &mut Model, _metrics: &Metrics) -> Control {
+        Control::Continue
+    }
+
+    fn on_epoch_end(&mut self, _model: &mut Model, _metrics: &Metrics) -> Control {
+        Control::Continue
+    }
+
+    fn on_train_end(&mut self, _model: &mut Model, _history: &History) {}
+}
+
+/// Closures are called at the end of every epoch.
+impl<F> Callback for F
+where
+    F: FnMut(&mut Model, &Metrics) -> Control + Send,
+{
+    fn on_epoch_end(&mut self, model: &mut Model, metrics: &Metrics) -> Control {
+        self(model, metrics)
+    }
+}
+
+/// Drives a model over any `Dataset` for a number of epochs of mini-batches.
+pub struct Trainer {
+    epochs: usize,
+    batch_size: Option<usize>,
+    optimizer: Box<dyn Optimizer>,
+    scheduler: Scheduler,
+    loss: Option<Arc<dyn LossFn>>,
+    callbacks: Vec<Box<dyn Callback>>,
+    validation: Option<TrainingBatch>,
+    validate_every: Option<usize>,
+    checkpoints: Option<Checkpoints>,
+    shuffle: Option<u64>,
+    drop_last: bool,
+    augmentation: Option<Augmenter>,
+    sampler: Option<WeightedSampler>,
+    position: Position,
+}
+
+// where training continues, set by `Trainer::resume`
+#[derive(Clone, Debug, Default)]
+struct Position {
+    epoch: usize,
+    batch: usize,
+    batch_size: usize,
+    epoch_cost: f64,
+}
+
+impl Trainer {
+    /// Full batch gradient descent with a rate of 1 until configured otherwise.
+    pub fn new(epochs: usize) -> Trainer {
+        Trainer {
+            epochs,
+            batch_size: None,
+            optimizer: Box::new(Sgd),
+            scheduler: Scheduler::new(Constant(1.0)),
+            loss: None,
+            callbacks: Vec::new(),
+            validation: None,
+            validate_every: None,
+            checkpoints: None,
+            shuffle: None,
+            drop_last: false,
+            augmentation: None,
+            sampler: None,
+            position: Position::default(),
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Trainer {
+        assert!(batch_size > 0);
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    pub fn with_optimizer(mut self, optimizer: impl Optimizer + 'static) -> Trainer {
+        self.optimizer = Box::new(optimizer);
+        self
+    }
+
+    /// Visits the samples in a new seeded permutation every epoch instead of in order.
+    pub fn with_shuffle(mut self, seed: u64) -> Trainer {
+        self.shuffle = Some(seed);
+        self
+    }
+
+    /// Draws every epoch from the sampler instead of visiting each sample
+    /// once, takes precedence over `with_shuffle`.
+    pub fn with_sampler(mut self, sampler: WeightedSampler) -> Trainer {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Skips the last mini-batch of an epoch if it is smaller than `batch_size`.
+    pub fn with_drop_last(mut self) -> Trainer {
+        self.drop_last = true;
+        self
+    }
+
+    /// Augments every mini-batch with the key of its place in the run, see
+    /// `Augmenter::key`, validation data is used as is.
+    pub fn with_augmentation(mut self, augmenter: Augmenter) -> Trainer {
+        self.augmentation = Some(augmenter);
+        self
+    }
+
+    /// Advanced once per optimizer step, observes the epoch cost.
+    pub fn with_schedule(mut self, schedule: impl LrSchedule + 'static) -> Trainer {
+        self.scheduler = Scheduler::new(schedule);
+        self
+    }
+
+    pub fn with_rate(self, rate: f64) -> Trainer {
+        self.with_schedule(Constant(rate))
+    }
+
+    /// Replaces the model's loss before training.
+    pub fn with_loss(mut self, loss: impl LossFn + 'static) -> Trainer {
+        self.loss = Some(Arc::new(loss));
+        self
+    }
+
+    pub fn with_callback(mut self, callback: impl Callback + 'static) -> Trainer {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Held out samples, their cost is recorded as "val_cost" after every epoch.
+    /// Other datasets can be loaded with `Dataset::to_batch`.
+    pub fn with_validation(mut self, batch: TrainingBatch) -> Trainer {
+        assert!(!batch.is_empty(), "cannot validate on an empty batch");
+        self.validation = Some(batch);
+        self
+    }
+
+    /// Also validates every `steps` optimizer steps, visible to `on_batch_end`.
+    pub fn with_validation_interval(mut self, steps: usize) -> Trainer {
+        assert!(steps > 0);
+        self.validate_every = Some(steps);
+        self
+    }
+
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Trainer {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    pub fn epochs(&self) -> usize {
+        self.epochs
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Trains in train mode, the model's previous mode is restored afterwards.
+    ///
+    /// Panics if a checkpoint cannot be written, see `try_fit`.
+    pub fn fit(&mut self, model: &mut Model, data: &impl Dataset) -> History {
+        self.try_fit(model, data)
+            .expect("failed to write checkpoint")
+    }
+
+    /// Like `fit`, stops at the first checkpoint that cannot be written.
+    pub fn try_fit(&mut self, model: &mut Model, data: &impl Dataset) -> io::Result<History> {
+        assert!(!data.is_empty(), "cannot train on an empty dataset");
+        if let Some(loss) = &self.loss {
+            model.set_loss(loss.clone());
+        }
+        let mode = model.mode();
+        model.set_mode(Mode::Train);
+
+        let mut history = History::default();
+        let result = self.run(model, data, &mut history);
+        if result.is_ok() {
+            self.callbacks
+                .iter_mut()
+                .for_each(|c| c.on_train_end(model, &history));
+        }
+        model.set_mode(mode);
+        self.position = Position::default();
+        result.map(|_| history)
+    }
+
+    fn run(
+        &mut self,
+        model: &mut Model,
+        data: &impl Dataset,
+        history: &mut History,
+    ) -> io::Result<()> {
+        let samples = data.len();
+        let batch_size = self.batch_size.unwrap_or(samples).min(samples);
+        if self.position.batch > 0 && self.position.batch_size != batch_size {
+            return Err(save::invalid("resumed with a different batch size"));
+        }
+        let resumed = std::mem::take(&mut self.position);
+
+        for epoch in resumed.epoch..self.epochs {
+            let (first, mut epoch_cost) = if epoch == resumed.epoch {
+                (resumed.batch, resumed.epoch_cost)
+            } else {
+                (0, 0.0)
+            };
+            let mut seen = (first * batch_size).min(samples);
+
+            // the order only depends on seed and epoch, so resuming mid epoch
+            // revisits the same permutation
+            let mut batches = match (&self.sampler, self.shuffle) {
+                (Some(sampler), _) => sampler.epoch(data, batch_size, epoch),
+                (None, Some(seed)) => {
+                    data.shuffled(batch_size, &mut Rng::derive(seed, epoch as u64))
+                }
+                (None, None) => data.batches(batch_size),
+            };
+            if self.drop_last {
+                batches = batches.drop_last();
+            }
+
+            let count = batches.len();
+            for (index, mut chunk) in batches.enumerate().skip(first) {
+                if let Some(augmenter) = &self.augmentation {
+                    augmenter.augment(&mut chunk, Augmenter::key(epoch, count, index));
+                }
+                let (cost, gradients) = model.cost_and_gradient(&chunk);
+                let rate = self.scheduler.next_rate();
+                self.optimizer.step(model, &gradients, rate);
+                epoch_cost += cost * chunk.samples() as f64;
+                seen += chunk.samples();
+
+                let mut metrics = Metrics {
+                    epoch,
+                    step: self.scheduler.step(),
+                    values: BTreeMap::new(),
+                };
+                metrics.set("cost", cost);
+                metrics.set("rate", rate);
+                metrics.set("grad_norm", gradients.norm());
+                if let (Some(validation), Some(every)) = (&self.validation, self.validate_every) {
+                    if metrics.step % every == 0 {
+                        metrics.set("val_cost", validation_cost(model, validation));
+                    }
+                }
+                let control = self.notify(|c| c.on_batch_end(model, &metrics));
+
+                let every = self.checkpoints.as_ref().and_then(Checkpoints::interval);
+                if every.is_some_and(|n| metrics.step % n == 0) {
+                    let position = Position {
+                        epoch,
+                        batch: index + 1,
+                        batch_size,
+                        epoch_cost,
+                    };
+                    self.checkpoint(model, &metrics, &position)?;
+                }
+                if control == Control::Stop {
+                    history.stopped = true;
+                    return Ok(());
+                }
+            }
+
+            let mut metrics = Metrics {
+                epoch,
+                step: self.scheduler.step(),
+                values: BTreeMap::new(),
+            };
+            metrics.set("cost", epoch_cost / seen as f64);
+            self.scheduler.observe(epoch_cost / seen as f64);
+            if let Some(validation) = &self.validation {
+                metrics.set("val_cost", validation_cost(model, validation));
+            }
+            let control = self.notify(|c| c.on_epoch_end(model, &metrics));
+
+            let every = self.checkpoints.as_ref().map(Checkpoints::interval);
+            if every == Some(None) {
+                let position = Position {
+                    epoch: epoch + 1,
+                    batch: 0,
+                    batch_size,
+                    epoch_cost: 0.0,
+                };
+                self.checkpoint(model, &metrics, &position)?;
+            }
+            history.epochs.push(metrics);
+            if control == Control::Stop {
+                history.stopped = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self, model: &Model, metrics: &Metrics, position: &Position) -> io::Result<()> {
+        let Some(checkpoints) = &self.checkpoints else {
+            return Ok(());
+        };
+        let metric = checkpoints.metric().and_then(|m| metrics.get(m));
+        checkpoints.save(metrics.step, metric, |w| {
+            writeln!(
+                w,
+                "position {} {} {} {}",
+                position.epoch, position.batch, position.batch_size, position.epoch_cost
+            )?;
+            match self.shuffle {
+                Some(seed) => writeln!(w, "shuffle {}", seed)?,
+                None => writeln!(w, "shuffle none")?,
+            }
+            let states = model.net().rng_states();
+            write!(w, "rng {}", states.len())?;
+            for state in states {
+                write!(w, " {}", state)?;
+            }
+            writeln!(w)?;
+            self.scheduler.write_state(w)?;
+            writeln!(w, "optimizer")?;
+            self.optimizer.write_state(w)?;
+            model.write_to(&mut &mut *w)
+        })?;
+        Ok(())
+    }
+
+    /// Restores the model, optimizer, schedule, position and generator states
+    /// from a checkpoint, the next `fit` continues exactly where it was taken.
+    /// The trainer and model have to be configured like the interrupted run,
+    /// callback state such as `EarlyStopping` starts over.
+    pub fn resume(
+        &mut self,
+        model: &mut Model,
+        path: impl AsRef<Path>,
+        registry: &Registry,
+    ) -> io::Result<()> {
+        let mut reader = Reader::new(File::open(path)?)?;
+        reader.expect("snail_checkpoint")?;
+        reader.expect("1")?;
+        reader.usize()?;
+        reader.word()?;
+
+        reader.expect("position")?;
+        let position = Position {
+            epoch: reader.usize()?,
+            batch: reader.usize()?,
+            batch_size: reader.usize()?,
+            epoch_cost: reader.f64()?,
+        };
+        reader.expect("shuffle")?;
+        self.shuffle = match reader.word()?.as_str() {
+            "none" => None,
+            seed => Some(
+                seed.parse()
+                    .map_err(|_| save::invalid(format!("expected seed, found `{}`", seed)))?,
+            ),
+        };
+        reader.expect("rng")?;
+        let states = (0..reader.usize()?)
+            .map(|_| reader.u64())
+            .collect::<io::Result<Vec<u64>>>()?;
+        self.scheduler.read_state(&mut reader)?;
+        reader.expect("optimizer")?;
+        self.optimizer.read_state(&mut reader)?;
+        model.read_layers(&mut reader, registry)?;
+        if model.net().rng_states().len() != states.len() {
+            return Err(save::invalid("saved rng states do not match the model"));
+        }
+        model.net_mut().set_rng_states(&states);
+
+        self.position = position;
+        Ok(())
+    }
+
+    // every callback sees the event, any of them can stop training
+    fn notify(&mut self, mut event: impl FnMut(&mut dyn Callback) -> Control) -> Control {
+        let mut control = Control::Continue;
+        for callback in self.callbacks.iter_mut() {
+            if event(callback.as_mut()) == Control::Stop {
+                control = Control::Stop;
+            }
+        }
+        control
+    }
+}
+
+// cost in eval mode, so dropout and batch statistics do not add noise
+fn validation_cost(model: &mut Model, batch: &TrainingBatch) -> f64 {
+    let mode = model.mode();
+    model.set_mode(Mode::Eval);
+    let cost = model.cost(batch);
+    model.set_mode(mode);
+    cost
+}
+
+/// Stops once `metric` did not improve by more than `min_delta` for
+/// `patience` checks in a row and restores the best weights at the end.
+pub struct EarlyStopping {
+    pub metric: String,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub maximize: bool,
+    pub restore_best: bool,
+    pub every_step: bool,
+    best: Option<(f64, Model)>,
+    wait: usize,
+}
+
+impl EarlyStopping {
+    /// Checked at the end of every epoch, lower values are better.
+    pub fn new(metric: &str, patience: usize) -> EarlyStopping {
+        EarlyStopping {
+            metric: metric.to_string(),
+            patience,
+            min_delta: 0.0,
+            maximize: false,
+            restore_best: true,
+            every_step: false,
+            best: None,
+            wait: 0,
+        }
+    }
+
+    pub fn with_min_delta(mut self, min_delta: f64) -> EarlyStopping {
+        self.min_delta = min_delta;
+        self
+    }
+
+    /// For metrics like accuracy where higher is better.
+    pub fn maximize(mut self) -> EarlyStopping {
+        self.maximize = true;
+        self
+    }
+
+    pub fn without_restore(mut self) -> EarlyStopping {
+        self.restore_best = false;
+        self
+    }
+
+    /// Checks after every step that recorded the metric, e.g. with
+    /// `Trainer::with_validation_interval`, patience then counts those steps.
+    pub fn every_step(mut self) -> EarlyStopping {
+        self.every_step = true;
+        self
+    }
+
+    pub fn best(&self) -> Option<f64> {
+        self.best.as_ref().map(|(value, _)| *value)
+    }
+
+    fn check(&mut self, model: &Model, metrics: &Metrics) -> Control {
+        let Some(value) = metrics.get(&self.metric) else {
+            return Control::Continue;
+        };
+        let improved = match &self.best {
+            None => true,
+            Some((best, _)) if self.maximize => value > best + self.min_delta,
+            Some((best, _)) => value < best - self.min_delta,
+        };
+
+        if improved {
+            self.best = Some((value, model.clone()));
+            self.wait = 0;
+            return Control::Continue;
+        }
+        self.wait += 1;
+        if self.wait >= self.patience {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_batch_end(&mut self, model: &mut Model, metrics: &Metrics) -> Control {
+        if self.every_step {
+            self.check(model, metrics)
+        } else {
+            Control::Continue
+        }
+    }
+
+    fn on_epoch_end(&mut self, model: &mut Model, metrics: &Metrics) -> Control {
+        if self.every_step {
+            Control::Continue
+        } else {
+            self.check(model, metrics)
+        }
+    }
+
+    fn on_train_end(&mut self, model: &mut Model, _history: &History) {
+        if let (true, Some((_, best))) = (self.restore_best, &self.best) {
+            model.clone_from(best);
+        }
+    }
+}
+
+#[cfg(test)]
+fn xor() -> TrainingBatch {
+    use crate::mat::MatF64;
+
+    TrainingBatch::new(
+        crate::mat!((0, 1), (1, 0), (1, 1), (0, 0)),
+        crate::mat!((1), (1), (0), (0)),
+    )
+}
+
+#[test]
+fn test_trainer_fit() {
+    use crate::optim::Adam;
+
+    let batch = xor();
+    let mut model = Model::new(&[2, 4, 1]);
+    let before = model.cost(&batch);
+
+    let mut trainer = Trainer::new(200)
+        .with_batch_size(2)
+        .with_optimizer(Adam::new())
+        .with_rate(0.05);
+    let history = trainer.fit(&mut model, &batch);
+
+    assert_eq!(history.epochs.len(), 200);
+    assert!(!history.stopped);
+    assert_eq!(history.last().unwrap().step, 400);
+    assert_eq!(trainer.scheduler().step(), 400);
+    assert!(model.cost(&batch) < before);
+
+    // 4 samples in batches of 3, the partial one is dropped
+    let mut trainer = Trainer::new(5)
+        .with_batch_size(3)
+        .with_shuffle(1)
+        .with_drop_last();
+    trainer.fit(&mut model, &batch);
+    assert_eq!(trainer.scheduler().step(), 5);
+}
+
+#[test]
+fn test_callbacks_stop() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    struct Counter {
+        batches: Arc<AtomicUsize>,
+        ended: Arc<AtomicUsize>,
+    }
+
+    impl Callback for Counter {
+        fn on_batch_end(&mut self, _model: &mut Model, metrics: &Metrics) -> Control {
+            assert!(metrics.get("grad_norm").is_some());
+            self.batches.fetch_add(1, Ordering::SeqCst);
+            Control::Continue
+        }
+
+        fn on_train_end(&mut self, _model: &mut Model, history: &History) {
+            self.ended.store(history.epochs.len(), Ordering::SeqCst);
+        }
+    }
+
+    let batches = Arc::new(AtomicUsize::new(0));
+    let ended = Arc::new(AtomicUsize::new(0));
+    let mut model = Model::new(&[2, 3, 1]);
+    let history = Trainer::new(100)
+        .with_batch_size(3)
+        .with_callback(Counter {
+            batches: batches.clone(),
+            ended: ended.clone(),
+        })
+        .with_callback(|_: &mut Model, metrics: &Metrics| {
+            if metrics.epoch == 4 {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        })
+        .fit(&mut model, &xor());
+
+    assert!(history.stopped);
+    assert_eq!(history.values("cost").len(), 5);
+    // 4 samples in batches of 3 and 1
+    assert_eq!(batches.load(Ordering::SeqCst), 10);
+    assert_eq!(ended.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn test_early_stopping_restores_best() {
+    use crate::mat::MatF64;
+
+    // noise targets, so validation cost eventually rises while training cost falls
+    let train = TrainingBatch::new(MatF64::rand(16, 3), MatF64::rand(16, 1));
+    let validation = TrainingBatch::new(MatF64::rand(16, 3), MatF64::rand(16, 1));
+    let mut model = Model::new(&[3, 16, 1]);
+
+    let history = Trainer::new(2000)
+        .with_rate(2.0)
+        .with_validation(validation.clone())
+        .with_callback(EarlyStopping::new("val_cost", 5))
+        .fit(&mut model, &train);
+
+    let val_costs = history.values("val_cost");
+    assert_eq!(val_costs.len(), history.epochs.len());
+    let best = val_costs.iter().cloned().fold(f64::INFINITY, f64::min);
+    model.eval();
+    assert!((model.cost(&validation) - best).abs() < 1e-12);
+    if history.stopped {
+        assert!(val_costs.last().unwrap() >= &best);
+    }
+}
+
+#[test]
+fn test_validation_interval() {
+    use std::sync::Mutex;
+
+    struct Steps(Arc<Mutex<Vec<usize>>>);
+    impl Callback for Steps {
+        fn on_batch_end(&mut self, _model: &mut Model, metrics: &Metrics) -> Control {
+            if metrics.get("val_cost").is_some() {
+                self.0.lock().unwrap().push(metrics.step);
+            }
+            Control::Continue
+        }
+    }
+
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    let mut model = Model::new(&[2, 2, 1]);
+    Trainer::new(3)
+        .with_batch_size(1)
+        .with_validation(xor())
+        .with_validation_interval(5)
+        .with_callback(|_: &mut Model, metrics: &Metrics| {
+            assert!(metrics.get("val_cost").is_some());
+            Control::Continue
+        })
+        .with_callback(Steps(steps.clone()))
+        .fit(&mut model, &xor());
+    assert_eq!(*steps.lock().unwrap(), vec![5, 10]);
+}
+
+#[test]
+fn test_resume_exact() {
+    use crate::{checkpoint::test_dir, mat::MatF64, optim::Adam, schedule::ReduceOnPlateau};
+
+    let batch = TrainingBatch::new(MatF64::rand(8, 2), MatF64::rand(8, 1));
+    let trainer = |dir: &Path| {
+        Trainer::new(6)
+            .with_batch_size(3)
+            .with_shuffle(5)
+            .with_optimizer(Adam::new())
+            .with_schedule(ReduceOnPlateau::new(0.05, 0.5, 0))
+            .with_checkpoints(Checkpoints::new(dir).every(4).keep_last(2))
+    };
+    let mut initial = Model::new(&[2, 4, 1]);
+    initial.set_dropout(0, 0.3);
+    initial.set_seed(11);
+
+    let uninterrupted_dir = test_dir("resume_uninterrupted");
+    let mut uninterrupted = initial.clone();
+    trainer(&uninterrupted_dir).fit(&mut uninterrupted, &batch);
+
+    // crash after step 10, the last checkpoint is from step 8 in the middle of epoch 2
+    let dir = test_dir("resume_crashed");
+    let history = trainer(&dir)
+        .with_callback(CrashAt(10))
+        .fit(&mut initial.clone(), &batch);
+    assert!(history.stopped);
+    let saved: Vec<usize> = Checkpoints::new(&dir)
+        .list()
+        .unwrap()
+        .iter()
+        .map(|s| s.step)
+        .collect();
+    assert_eq!(saved, vec![4, 8]);
+
+    let mut resumed = Model::new(&[2, 4, 1]);
+    resumed.set_dropout(0, 0.3);
+    let mut trainer = trainer(&dir);
+    let latest = Checkpoints::new(&dir).latest().unwrap().unwrap();
+    trainer
+        .resume(&mut resumed, latest, &Registry::default())
+        .unwrap();
+    let history = trainer.fit(&mut resumed, &batch);
+
+    assert_eq!(history.epochs.first().unwrap().epoch, 2);
+    assert_eq!(trainer.scheduler().step(), 18);
+    assert_eq!(resumed.flat_params(), uninterrupted.flat_params());
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(&uninterrupted_dir).unwrap();
+}
+
+#[cfg(test)]
+struct CrashAt(usize);
+
+#[cfg(test)]
+impl Callback for CrashAt {
+    fn on_batch_end(&mut self, _model: &mut Model, metrics: &Metrics) -> Control {
+        if metrics.step == self.0 {
+            Control::Stop
+        } else {
+            Control::Continue
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    dataset::{Batches, Dataset},
+    rng::Rng,
+};
+
+/// Balanced class weights `samples / (classes * count)`, indexed by class.
+/// Classes without samples get zero, the present ones average one per sample.
+pub fn class_weights(labels: &[usize]) -> Vec<f64> {
+    let classes = labels.iter().max().map_or(0, |l| l + 1);
+    let mut counts = vec![0usize; classes];
+    labels.iter().for_each(|l| counts[*l] += 1);
+    let present = counts.iter().filter(|c| **c > 0).count();
+    counts
+        .iter()
+        .map(|c| match c {
+            0 => 0.0,
+            c => labels.len() as f64 / (present * c) as f64,
+        })
+        .collect()
+}
+
+/// The weight of every sample's class, e.g. for `TrainingBatch::with_weights`.
+pub fn sample_weights(labels: &[usize], class_weights: &[f64]) -> Vec<f64> {
+    labels
+        .iter()
+        .map(|l| {
+            *class_weights
+                .get(*l)
+                .unwrap_or_else(|| panic!("no weight for class {}", l))
+        })
+        .collect()
+}
+
+/// Draws samples with replacement, each with probability proportional to
+/// its weight, to oversample rare classes instead of weighting them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightedSampler {
+    // running sum of the weights
+    cumulative: Vec<f64>,
+    seed: u64,
+}
+
+impl WeightedSampler {
+    pub fn new(weights: &[f64], seed: u64) -> WeightedSampler {
+        assert!(
+            weights.iter().all(|w| *w >= 0.0 && w.is_finite()),
+            "sample weights must be finite and not negative"
+        );
+        let mut total = 0.0;
+        let cumulative = weights
+            .iter()
+            .map(|w| {
+                total += w;
+                total
+            })
+            .collect::<Vec<f64>>();
+        assert!(total > 0.0, "sample weights sum to zero");
+        WeightedSampler { cumulative, seed }
+    }
+
+    /// Every class drawn equally often on average.
+    pub fn balanced(labels: &[usize], seed: u64) -> WeightedSampler {
+        WeightedSampler::new(&sample_weights(labels, &class_weights(labels)), seed)
+    }
+
+    /// Number of weighted samples.
+    pub fn len(&self) -> usize {
+        self.cumulative.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cumulative.is_empty()
+    }
+
+    pub fn sample(&self, count: usize, rng: &mut Rng) -> Vec<usize> {
+        let total = *self.cumulative.last().unwrap();
+        (0..count)
+            .map(|_| {
+                let target = rng.uniform() * total;
+                self.cumulative
+                    .partition_point(|c| *c <= target)
+                    .min(self.len() - 1)
+            })
+            .collect()
+    }
+
+    /// Mini-batches of an epoch of as many draws as `data` has samples, the
+    /// draws only depend on the seed and `epoch`.
+    pub fn epoch<'a, D: Dataset + ?Sized>(
+        &self,
+        data: &'a D,
+        size: usize,
+        epoch: usize,
+    ) -> Batches<'a, D> {
+        assert_eq!(data.len(), self.len(), "one weight per sample");
+        let order = self.sample(data.len(), &mut Rng::derive(self.seed, epoch as u64));
+        Batches::new(data, order, size)
+    }
+}
+
+#[test]
+fn test_class_weights() {
+    let labels = [0, 0, 0, 2, 0, 0];
+    let weights = class_weights(&labels);
+    assert_eq!(weights, vec![0.6, 0.0, 3.0]);
+    let samples = sample_weights(&labels, &weights);
+    assert!((samples.iter().sum::<f64>() - labels.len() as f64).abs() < 1e-12);
+    assert_eq!(samples[3], 3.0);
+}
+
+#[test]
+fn test_weighted_sampler() {
+    use crate::{batch::TrainingBatch, nn::Model, train::Trainer};
+
+    // 1 positive in 100
+    let mut batch = TrainingBatch::empty(1, 1);
+    for i in 0..100 {
+        let label = if i == 42 { 1.0 } else { 0.0 };
+        batch.add(&[i as f64 / 100.0], &[label]);
+    }
+
+    let sampler = WeightedSampler::balanced(&batch.labels(), 3);
+    let drawn = sampler.sample(10_000, &mut Rng::new(1));
+    let positives = drawn.iter().filter(|i| **i == 42).count();
+    assert!((4_500..5_500).contains(&positives), "{}", positives);
+    assert!(drawn.iter().all(|i| *i < 100));
+    assert_eq!(
+        WeightedSampler::new(&[0.0, 1.0], 0).sample(5, &mut Rng::new(2)),
+        vec![1; 5]
+    );
+
+    let epoch = sampler.epoch(&batch, 10, 4);
+    assert_eq!(epoch.len(), 10);
+    assert_eq!(epoch.order(), sampler.epoch(&batch, 10, 4).order());
+    assert_ne!(epoch.order(), sampler.epoch(&batch, 10, 5).order());
+
+    let mut model = Model::new(&[1, 1]);
+    let mut trainer = Trainer::new(2).with_batch_size(10).with_sampler(sampler);
+    assert_eq!(trainer.fit(&mut model, &batch).epochs.len(), 2);
+    assert_eq!(trainer.scheduler().step(), 20);
+}